@@ -0,0 +1,310 @@
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use globset::GlobSet;
+use termtree::Tree;
+use zip::ZipArchive;
+
+use crate::{
+    counter::{FileCount, Options, content_count},
+    token::{
+        FileToken,
+        get_file_size,
+        size_limit_str,
+    },
+    tokenizer::Tokenizer,
+    traverse::glob_set,
+};
+
+/// 读取成员内容，最多读取指定的byte数，不按头信息中的大小预先分配内存
+type ReadMember<'a> = dyn FnMut(u64) -> std::io::Result<Vec<u8>> + 'a;
+/// 处理单个成员：(路径, 解压后的大小, 读取内容)
+type AddMember<'a> = dyn FnMut(&Path, u64, &mut ReadMember) -> Result<(), String> + 'a;
+
+/// 压缩包中成员组成的tree，文件夹的token数和大小包含其下所有文件
+struct Member {
+    name:     String,            // 文件或文件夹名
+    desc:     Option<String>,    // 文件大小、token数或没有计算token的原因，文件夹为None
+    tokens:   usize,             // token数
+    size:     u64,               // 解压后的大小
+    children: Vec<Member>,       // 子项，按压缩包中的顺序
+    leaves:   Vec<Tree<String>>, // 文件的子节点，例如notebook的cell、文档的页
+}
+
+impl Member {
+    /// 按路径插入文件，中间的文件夹不存在则创建
+    fn insert(&mut self, path: &Path, desc: String, tokens: usize, size: u64, leaves: Vec<Tree<String>>) {
+        self.tokens += tokens;
+        self.size += size;
+        let mut components = path.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<String>>();
+        let name = components.remove(0);
+        if components.is_empty() {
+            self.children.push(Member{name, desc: Some(desc), tokens, size, children: vec![], leaves});
+            return
+        }
+        let pos = match self.children.iter().position(|c| c.desc.is_none() && c.name == name) {
+            Some(p) => p,
+            None => {
+                self.children.push(Member{name, desc: None, tokens: 0, size: 0, children: vec![], leaves: vec![]});
+                self.children.len() - 1
+            },
+        };
+        self.children[pos].insert(&components.iter().collect::<PathBuf>(), desc, tokens, size, leaves);
+    }
+
+    /// 转为tree，格式同traverse_directory
+    fn tree(&self) -> Tree<String> {
+        let label = match &self.desc {
+            Some(d) => format!("{} ({})", self.name, d),
+            None if self.tokens == 0 => format!("{} ({}, total 0 token)", self.name, get_file_size(self.size)),
+            None => format!("{} ({}, total {} tokens)", self.name, get_file_size(self.size), self.tokens),
+        };
+        Tree::new(label).with_leaves(self.children.iter().map(|c| c.tree()).chain(self.leaves.iter().cloned()))
+    }
+}
+
+/// 读取的压缩包
+pub struct Archive {
+    pub tokens:  usize,             // 所有成员的总token数
+    pub members: usize,             // 包含在tree中的文件数
+    pub tree:    Vec<Tree<String>>, // 成员组成的tree，作为压缩包节点的子节点
+}
+
+impl Archive {
+    /// 显示在压缩包大小后面的描述
+    pub fn label(&self) -> String {
+        format!("archive {} files, total {} tokens", self.members, self.tokens)
+    }
+}
+
+/// 读取`.zip`、`.tar`、`.tar.gz`、`.tgz`压缩包，计算每个成员的token数，不是这些后缀返回None
+/// 成员同样遵循-i、-x、-m、-t、-T、-d以及提取器、notebook、二进制文件、编码检测的规则，压缩包损坏则返回错误信息
+pub fn read_archive(bpe: &dyn Tokenizer, file: &Path, raw: &[u8], options: &Options) -> Option<Result<Archive, String>> {
+    let name = file.file_name()?.to_string_lossy().to_lowercase();
+    let gzip = name.ends_with(".tar.gz") || name.ends_with(".tgz");
+    if !(name.ends_with(".zip") || name.ends_with(".tar") || gzip) {
+        return None
+    }
+    let (include, exclude) = match (glob_set(&options.include), glob_set(&options.exclude)) {
+        (Ok(i), Ok(e)) => (i, e),
+        (Err(e), _) | (_, Err(e)) => return Some(Err(e.to_string())),
+    };
+    let mut root = Member{name: String::new(), desc: None, tokens: 0, size: 0, children: vec![], leaves: vec![]};
+    let mut members = 0;
+    let mut add = |path: &Path, size: u64, read: &mut ReadMember| -> Result<(), String> {
+        let path = &path.components().filter(|c| !matches!(c, Component::CurDir)).collect::<PathBuf>(); // 去除`./`
+        if path.as_os_str().is_empty() || !matched(path, &include, &exclude) { // 没有文件名的成员（例如`./`、`.`）
+            return Ok(())
+        }
+        if let Some(file) = member(bpe, path, size, read, options).map_err(|e| format!("{}: {}", path.display(), e))? {
+            root.insert(path, file.label, file.tokens, size, file.children);
+            members += 1;
+        }
+        Ok(())
+    };
+    let result = if name.ends_with(".zip") {
+        read_zip(raw, &mut add)
+    } else if gzip {
+        read_tar(GzDecoder::new(raw), &mut add)
+    } else {
+        read_tar(raw, &mut add)
+    };
+    Some(result.map(|_| Archive{tokens: root.tokens, members, tree: root.children.iter().map(|c| c.tree()).collect()}))
+}
+
+/// 遍历zip中的每个文件
+fn read_zip(raw: &[u8], add: &mut AddMember) -> Result<(), String> {
+    let mut zip = ZipArchive::new(Cursor::new(raw)).map_err(|e| e.to_string())?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        if entry.is_dir() {
+            continue
+        }
+        let Some(path) = entry.enclosed_name() else { // 不安全的路径（例如`../x`）
+            continue
+        };
+        let size = entry.size();
+        add(&path, size, &mut |limit| {
+            let mut buf = vec![];
+            entry.by_ref().take(limit).read_to_end(&mut buf).map(|_| buf)
+        })?;
+    }
+    Ok(())
+}
+
+/// 遍历tar中的每个文件
+fn read_tar(reader: impl Read, add: &mut AddMember) -> Result<(), String> {
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        if !entry.header().entry_type().is_file() {
+            continue
+        }
+        let path = entry.path().map_err(|e| e.to_string())?.into_owned();
+        if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) { // 不安全的路径（例如`../x`、`/etc/x`），同zip
+            continue
+        }
+        let size = entry.size();
+        add(&path, size, &mut |limit| {
+            let mut buf = vec![];
+            entry.by_ref().take(limit).read_to_end(&mut buf).map(|_| buf)
+        })?;
+    }
+    Ok(())
+}
+
+/// 成员路径是否通过-i、-x过滤，-x匹配路径、任意上级文件夹或文件名，-i匹配路径或文件名
+fn matched(path: &Path, include: &GlobSet, exclude: &GlobSet) -> bool {
+    if path.ancestors().filter(|a| !a.as_os_str().is_empty()).any(|a| exclude.is_match(a) || a.file_name().is_some_and(|n| exclude.is_match(n))) {
+        return false
+    }
+    include.is_empty() || include.is_match(path) || path.file_name().is_some_and(|n| include.is_match(n))
+}
+
+/// 计算单个成员的token数，分类同counter::file_count（不打开嵌套的压缩包），被-t、-T、-d过滤的返回None
+/// 头信息中大小超过-m的成员不会解压，头信息可能与实际不符（例如zip炸弹），所以最多只解压-m+1个byte，超过-m的同样不计算token
+fn member(bpe: &dyn Tokenizer, path: &Path, size: u64, read: &mut ReadMember, options: &Options) -> std::io::Result<Option<FileCount>> {
+    let mut out = FileCount{path: path.to_path_buf(), size, tokens: 0, valid: false, label: String::new(), children: vec![], raw: None, breakdown: None, error: None};
+    if size > options.max_size {
+        out.label = format!("{}, file size {} bytes > {}", get_file_size(size), size, size_limit_str(options.max_size));
+    } else {
+        let raw = read(options.max_size.saturating_add(1))?;
+        if raw.len() as u64 > options.max_size {
+            out.label = format!("decompressed size > {}, header says {} bytes", size_limit_str(options.max_size), size);
+        } else {
+            let len = raw.len() as u64;
+            content_count(bpe, path, FileToken::from_bytes(raw, len).with_encoding(options.charset).with_binary(options.binary), options, &mut out);
+        }
+    }
+    Ok(out.kept(options).then_some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        token::SpecialTokens,
+        tokenizer::test_tokenizer,
+    };
+
+    /// 在内存中创建tar，直接写入头信息中的路径，以便包含`./`、`../x`等路径
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// 在内存中创建zip，不压缩
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+            std::io::Write::write_all(&mut writer, data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// tree的每一行
+    fn lines(archive: &Archive) -> Vec<String> {
+        archive.tree.iter().flat_map(|t| t.to_string().lines().map(|l| l.to_string()).collect::<Vec<String>>()).collect()
+    }
+
+    #[test]
+    fn empty_and_unsafe_paths() {
+        let raw = tar(&[("./", b"x"), (".", b"y"), ("../evil.txt", b"z"), ("/etc/abs.txt", b"w"), ("./docs/a.txt", b"hello")]);
+        let archive = read_archive(test_tokenizer(), Path::new("a.tar"), &raw, &Options::default()).unwrap().unwrap();
+        assert_eq!(archive.members, 1);
+        assert_eq!(lines(&archive), ["docs (5 bytes, total 1 tokens)", "└── a.txt (5 bytes, 1 tokens)"]);
+    }
+
+    #[test]
+    fn zip_nested() {
+        let raw = zip(&[("README.md", b"hello world"), ("src/lib/a.rs", b"fn main() {}"), ("src/b.bin", b"\x00\x01\x02"), ("src/", b"")]);
+        let archive = read_archive(test_tokenizer(), Path::new("a.ZIP"), &raw, &Options::default()).unwrap().unwrap();
+        assert_eq!(archive.members, 3);
+        assert_eq!(archive.tokens, 2 + 4);
+        assert_eq!(lines(&archive), [
+            "README.md (11 bytes, 2 tokens)",
+            "src (15 bytes, total 4 tokens)",
+            "├── lib (12 bytes, total 4 tokens)",
+            "│   └── a.rs (12 bytes, 4 tokens)",
+            "└── b.bin (3 bytes, binary file: NUL byte at offset 0)",
+        ]);
+        assert!(read_archive(test_tokenizer(), Path::new("a.txt"), &raw, &Options::default()).is_none());
+        assert!(read_archive(test_tokenizer(), Path::new("bad.zip"), b"not a zip", &Options::default()).unwrap().is_err());
+    }
+
+    #[test]
+    fn tar_gz() {
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut gz, &tar(&[("pkg/a.txt", b"hello"), ("pkg/docs/b.txt", b"hello world")])).unwrap();
+        let raw = gz.finish().unwrap();
+        for name in ["a.tar.gz", "a.tgz"] {
+            let archive = read_archive(test_tokenizer(), Path::new(name), &raw, &Options::default()).unwrap().unwrap();
+            assert_eq!((archive.members, archive.tokens), (2, 3));
+            assert_eq!(lines(&archive)[0], "pkg (16 bytes, total 3 tokens)");
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let raw = zip(&[("a.txt", b"hello"), ("target/b.txt", b"hello world"), ("c.md", b"hello world"), ("e.txt", b"")]);
+        // -x匹配上级文件夹或文件名
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &Options::default().exclude(["target", "*.md"])).unwrap().unwrap();
+        assert_eq!(lines(&archive), ["a.txt (5 bytes, 1 tokens)", "e.txt (0 bytes, 0 token)"]);
+        // -i和-d
+        let mut options = Options::default().include(["*.txt"]).only_valid(true);
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &options).unwrap().unwrap();
+        assert_eq!(lines(&archive), ["a.txt (5 bytes, 1 tokens)", "target (11 bytes, total 2 tokens)", "└── b.txt (11 bytes, 2 tokens)"]);
+        // -t
+        options = Options::default().tokens(2, usize::MAX);
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &options).unwrap().unwrap();
+        assert_eq!(archive.members, 3);
+        assert!(!lines(&archive).iter().any(|l| l.contains("a.txt")));
+    }
+
+    #[test]
+    fn oversize() {
+        let big = vec![b'a'; 2048];
+        let raw = zip(&[("big.txt", &big), ("small.txt", b"hello")]);
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &Options::default().max_size(1024)).unwrap().unwrap();
+        assert_eq!(lines(&archive), ["big.txt (2.00Kb, file size 2048 bytes > 1Kb)", "small.txt (5 bytes, 1 tokens)"]);
+        // 头信息中的大小与实际不符时只解压-m+1个byte
+        let mut calls = vec![];
+        let out = member(test_tokenizer(), Path::new("lie.txt"), 10, &mut |limit| {
+            calls.push(limit);
+            Ok(vec![b'a'; limit as usize])
+        }, &Options::default().max_size(1024)).unwrap().unwrap();
+        assert_eq!(calls, [1025]);
+        assert_eq!((out.label.as_str(), out.valid), ("decompressed size > 1Kb, header says 10 bytes", false));
+        assert!(member(test_tokenizer(), Path::new("lie.txt"), 10, &mut |limit| Ok(vec![b'a'; limit as usize]), &Options::default().max_size(1024).only_valid(true)).unwrap().is_none());
+    }
+
+    #[test]
+    fn same_classification_as_files() {
+        // notebook和提取器同样用于成员，cell、页作为成员的子节点
+        let notebook = br##"{"cells": [{"cell_type": "markdown", "source": "# Title"}, {"cell_type": "code", "source": "print(1)"}]}"##;
+        let raw = zip(&[("nb.ipynb", notebook), ("page.html", b"<html><body><p>hello world</p></body></html>")]);
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &Options::default().extract()).unwrap().unwrap();
+        assert_eq!(lines(&archive), [
+            "nb.ipynb (104 bytes, notebook 2 cells, 11 tokens, raw JSON 33 tokens, saves 22 tokens (66.7%))",
+            "├── [1] markdown (2 tokens)",
+            "└── [2] code (8 tokens)",
+            "page.html (44 bytes, extracted HTML text 2 tokens, raw markup 15 tokens, saves 13 tokens (86.7%))",
+        ]);
+        assert_eq!(archive.tokens, 11 + 2);
+        // 不允许特殊token
+        let raw = zip(&[("a.txt", b"x<|endoftext|>")]);
+        let archive = read_archive(test_tokenizer(), Path::new("a.zip"), &raw, &Options::default().special(SpecialTokens::Disallow)).unwrap().unwrap();
+        assert_eq!(lines(&archive), ["a.txt (14 bytes, contain special tokens: <|endoftext|> at 1:2)"]);
+    }
+}
@@ -0,0 +1,150 @@
+/// 二进制文件的检测方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryDetection {
+    Off,      // 不检测，所有文件都作为文本
+    Fast,     // 检查文件头标识，以及采样范围内是否有NUL
+    Thorough, // 检查文件头标识，以及采样范围内NUL和控制字符的比例
+}
+
+/// thorough时NUL的比例超过该值则认为是二进制文件
+const NUL_RATIO: f64 = 0.01;
+/// thorough时控制字符（不包括NUL、DEL和`\t`、`\n`、`\r`、`\x0b`、`\x0c`、`\x1b`）的比例超过该值则认为是二进制文件
+const CONTROL_RATIO: f64 = 0.1;
+
+/// 常见二进制格式的文件头标识，(偏移, 标识, 格式名称)
+const SIGNATURES: [(usize, &[u8], &str); 20] = [
+    (0, b"%PDF-", "PDF"),
+    (0, b"\x7fELF", "ELF"),
+    (0, b"\xfe\xed\xfa\xce", "Mach-O"),
+    (0, b"\xfe\xed\xfa\xcf", "Mach-O"),
+    (0, b"\xce\xfa\xed\xfe", "Mach-O"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O"),
+    (0, b"\xca\xfe\xba\xbe", "Mach-O fat binary or Java class"),
+    (0, b"\0asm", "WebAssembly"),
+    (0, b"PK\x03\x04", "ZIP"),
+    (0, b"\x1f\x8b", "gzip"),
+    (0, b"BZh", "bzip2"),
+    (0, b"\xfd7zXZ\0", "xz"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7z"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd"),
+    (0, b"Rar!\x1a\x07", "RAR"),
+    (257, b"ustar", "tar"),
+    (0, b"SQLite format 3\0", "SQLite"),
+    (0, b"OggS", "Ogg"),
+    (0, b"fLaC", "FLAC"),
+    (0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "OLE2 (doc, xls, msi)"),
+];
+
+/// 可配置的二进制文件分类器
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryClassifier {
+    pub detection: BinaryDetection, // 检测方式，默认fast
+    pub sample:    usize,           // 检查文件开头的byte数，0表示整个文件，fast默认8192，thorough默认整个文件
+}
+
+impl Default for BinaryClassifier {
+    fn default() -> Self {
+        BinaryClassifier{detection: BinaryDetection::Fast, sample: 8192}
+    }
+}
+
+impl BinaryClassifier {
+    /// 判断是否是二进制文件，是则返回判断的原因，例如`PDF signature`、`NUL byte at offset 12`、`3.2% NUL bytes`
+    pub fn classify(&self, raw: &[u8]) -> Option<String> {
+        if self.detection == BinaryDetection::Off {
+            return None
+        }
+        if let Some(name) = signature(raw) {
+            return Some(format!("{} signature", name))
+        }
+        let sample = if self.sample == 0 { raw } else { &raw[..raw.len().min(self.sample)] };
+        match self.detection {
+            BinaryDetection::Fast => sample.iter().position(|b| *b == 0).map(|i| format!("NUL byte at offset {}", i)),
+            _ => {
+                if sample.is_empty() {
+                    return None
+                }
+                let nul = sample.iter().filter(|b| **b == 0).count() as f64 / sample.len() as f64;
+                let control = sample.iter().filter(|b| b.is_ascii_control() && !b"\t\n\r\x0b\x0c\x1b\x7f\0".contains(b)).count() as f64 / sample.len() as f64;
+                if nul > NUL_RATIO {
+                    Some(format!("{:.1}% NUL bytes", nul * 100.0))
+                } else if control > CONTROL_RATIO {
+                    Some(format!("{:.1}% control characters", control * 100.0))
+                } else {
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// 根据文件头标识判断常见二进制格式，Windows可执行文件还要检查PE头
+fn signature(raw: &[u8]) -> Option<&'static str> {
+    if let Some((_, _, name)) = SIGNATURES.iter().find(|(offset, magic, _)| raw.get(*offset..offset + magic.len()) == Some(*magic)) {
+        return Some(name)
+    }
+    if raw.starts_with(b"MZ") && let Some(b) = raw.get(0x3c..0x40) {
+        let pe = u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+        if raw.get(pe..pe + 4) == Some(b"PE\0\0") {
+            return Some("PE executable")
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier(detection: BinaryDetection, sample: usize) -> BinaryClassifier {
+        BinaryClassifier{detection, sample}
+    }
+
+    #[test]
+    fn signatures() {
+        let fast = BinaryClassifier::default();
+        assert_eq!(fast.classify(b"%PDF-1.7 text"), Some("PDF signature".to_string()));
+        assert_eq!(fast.classify(b"\x1f\x8b\x08"), Some("gzip signature".to_string()));
+        let mut tar = vec![b'a'; 300];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(fast.classify(&tar), Some("tar signature".to_string()));
+        // MZ开头还要有PE头
+        let mut pe = vec![b'a'; 0x50];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c..0x40].copy_from_slice(&0x44u32.to_le_bytes());
+        assert_eq!(signature(&pe), None);
+        pe[0x44..0x48].copy_from_slice(b"PE\0\0");
+        assert_eq!(fast.classify(&pe), Some("PE executable signature".to_string()));
+    }
+
+    #[test]
+    fn off() {
+        assert_eq!(classifier(BinaryDetection::Off, 0).classify(b"%PDF-\0\0\0"), None);
+    }
+
+    #[test]
+    fn fast_nul_within_sample() {
+        let mut raw = vec![b'a'; 100];
+        raw[50] = 0;
+        assert_eq!(classifier(BinaryDetection::Fast, 0).classify(&raw), Some("NUL byte at offset 50".to_string()));
+        assert_eq!(classifier(BinaryDetection::Fast, 50).classify(&raw), None); // NUL在采样范围之外
+        assert_eq!(classifier(BinaryDetection::Fast, 51).classify(&raw), Some("NUL byte at offset 50".to_string()));
+        assert_eq!(classifier(BinaryDetection::Fast, 0).classify(b"plain text\n\t\x1b[0m"), None);
+    }
+
+    #[test]
+    fn thorough_ratios() {
+        let thorough = classifier(BinaryDetection::Thorough, 0);
+        let mut raw = vec![b'a'; 1000];
+        raw[10] = 0; // 0.1%的NUL不超过比例
+        assert_eq!(thorough.classify(&raw), None);
+        raw[..20].fill(0);
+        assert_eq!(thorough.classify(&raw), Some("2.0% NUL bytes".to_string()));
+        let mut raw = vec![b'a'; 1000];
+        raw[..150].fill(0x01);
+        assert_eq!(thorough.classify(&raw), Some("15.0% control characters".to_string()));
+        raw[..150].fill(b'\n'); // 换行等常见控制字符不计入
+        assert_eq!(thorough.classify(&raw), None);
+        assert_eq!(thorough.classify(b""), None);
+    }
+}
@@ -0,0 +1,167 @@
+use std::ops::Range;
+use std::path::Path;
+
+use tree_sitter::{Node, Parser};
+
+use crate::{
+    normalize::Normalize,
+    symbols::language,
+    token::{
+        SpecialTokens,
+        count,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 各语言字符串字面量的节点类型
+const STRING_KINDS: [&str; 9] = [
+    "string_literal", "raw_string_literal", "char_literal", // Rust、Go、Java
+    "string", "template_string",                          // Python、TypeScript
+    "interpreted_string_literal", "rune_literal",         // Go
+    "text_block", "character_literal",                    // Java
+];
+
+/// 源代码文件的token数分类，以及去除注释、规范化空白后的token数
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Breakdown {
+    pub code:       usize, // 代码token数
+    pub comment:    usize, // 注释token数
+    pub string:     usize, // 字符串字面量token数
+    pub whitespace: usize, // 只含空白（缩进、空行等）的token数
+    pub stripped:   usize, // 去除注释后的token数
+    pub normalized: usize, // 规范化空白（CRLF转为LF、去除行尾空白、合并连续空行）后的token数
+}
+
+impl Breakdown {
+    /// 显示在文件token数后面的额外列
+    pub fn columns(&self) -> String {
+        format!("code {}, comment {}, string {}, whitespace {}, {} without comments, {} with normalized whitespace", self.code, self.comment, self.string, self.whitespace, self.stripped, self.normalized)
+    }
+
+    /// 累加另一个文件的结果
+    pub fn add(&mut self, other: &Breakdown) {
+        self.code += other.code;
+        self.comment += other.comment;
+        self.string += other.string;
+        self.whitespace += other.whitespace;
+        self.stripped += other.stripped;
+        self.normalized += other.normalized;
+    }
+
+    /// 总token数
+    pub fn total(&self) -> usize {
+        self.code + self.comment + self.string + self.whitespace
+    }
+}
+
+/// 将源代码文件的token分为代码、注释、字符串字面量、空白4类，并计算去除注释、规范化空白后的token数
+/// 每个token按起始byte位置分类，只含空白字符的token计为空白，支持的语言同`--symbols`，不支持的语言返回None
+pub fn breakdown(bpe: &dyn Tokenizer, file: &Path, text: &str, special: SpecialTokens) -> Option<Breakdown> {
+    let mut parser = Parser::new();
+    parser.set_language(&language(file)?).ok()?;
+    let tree = parser.parse(text, None)?;
+    let mut comments: Vec<Range<usize>> = vec![];
+    let mut strings: Vec<Range<usize>> = vec![];
+    collect_ranges(tree.root_node(), text, &mut comments, &mut strings);
+    let in_ranges = |ranges: &[Range<usize>], pos: usize| ranges.get(ranges.partition_point(|r| r.end <= pos)).is_some_and(|r| r.start <= pos);
+    let mut out = Breakdown::default();
+    let offsets = token_offsets(bpe, text, special);
+    let bytes = text.as_bytes();
+    for w in offsets.windows(2) {
+        if in_ranges(&comments, w[0]) {
+            out.comment += 1;
+        } else if in_ranges(&strings, w[0]) {
+            out.string += 1;
+        } else if bytes[w[0]..w[1]].iter().all(|b| b.is_ascii_whitespace()) {
+            out.whitespace += 1;
+        } else {
+            out.code += 1;
+        }
+    }
+    out.stripped = count(bpe, &strip_comments(text, &comments), special);
+    out.normalized = count(bpe, &Normalize{crlf: true, trailing: true, blank: true, ..Default::default()}.apply(text), special);
+    Some(out)
+}
+
+/// 按顺序递归获取注释和字符串字面量的byte范围，不进入这些节点内部，注释范围不包含结尾的换行符
+fn collect_ranges(node: Node, text: &str, comments: &mut Vec<Range<usize>>, strings: &mut Vec<Range<usize>>) {
+    if node.kind().contains("comment") {
+        let range = node.byte_range();
+        let end = range.start + text[range.clone()].trim_end_matches(['\r', '\n']).len();
+        comments.push(range.start..end);
+    } else if STRING_KINDS.contains(&node.kind()) {
+        strings.push(node.byte_range());
+    } else {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect_ranges(child, text, comments, strings);
+        }
+    }
+}
+
+/// 去除注释，去除后只剩空白的行整行删除，含注释的行去除行尾空白
+fn strip_comments(text: &str, comments: &[Range<usize>]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut start = 0; // 当前行的起始byte位置
+    for line in text.split_inclusive('\n') {
+        let end = start + line.len();
+        let mut kept = String::new();
+        let mut pos = start;
+        for r in comments.iter().filter(|r| r.start < end && r.end > start) {
+            if r.start > pos {
+                kept += &text[pos..r.start];
+            }
+            pos = pos.max(r.end);
+        }
+        if pos == start { // 该行没有注释
+            out += line;
+        } else {
+            if pos < end {
+                kept += &text[pos..end];
+            }
+            let content = kept.trim_end();
+            if !content.trim_start().is_empty() {
+                out += content;
+                out += &line[line.trim_end_matches(['\r', '\n']).len()..]; // 保留原来的换行符
+            }
+        }
+        start = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    const RUST: &str = "// add two numbers\nfn add(a: i32, b: i32) -> i32 {   \n    a + b // sum\n}\n\n\n\nfn main() {\n    let s = \"hello world\";\n    println!(\"{} {}\", s, add(1, 2));\n}\n";
+    const PYTHON: &str = "# greet\ndef greet(name):\n    \"\"\"Say hi.\"\"\"\n    return 'hi ' + name  # trailing\n";
+
+    #[test]
+    fn rust() {
+        let b = breakdown(test_tokenizer(), Path::new("a.rs"), RUST, SpecialTokens::Allow).unwrap();
+        // 4类之和等于文件的token数
+        assert_eq!(b.total(), count(test_tokenizer(), RUST, SpecialTokens::Allow));
+        assert_eq!((b.comment, b.string), (5, 5));
+        assert!(b.code > 0 && b.whitespace > 0);
+        let stripped = "fn add(a: i32, b: i32) -> i32 {   \n    a + b\n}\n\n\n\nfn main() {\n    let s = \"hello world\";\n    println!(\"{} {}\", s, add(1, 2));\n}\n";
+        assert_eq!(b.stripped, count(test_tokenizer(), stripped, SpecialTokens::Allow));
+        assert!(b.normalized < b.total()); // 去除行尾空白、合并连续空行
+    }
+
+    #[test]
+    fn python() {
+        let b = breakdown(test_tokenizer(), Path::new("a.py"), PYTHON, SpecialTokens::Allow).unwrap();
+        assert_eq!(b.total(), count(test_tokenizer(), PYTHON, SpecialTokens::Allow));
+        assert!(b.comment > 0 && b.string > 0 && b.code > 0);
+        assert_eq!(b.stripped, count(test_tokenizer(), "def greet(name):\n    \"\"\"Say hi.\"\"\"\n    return 'hi ' + name\n", SpecialTokens::Allow));
+        // 多个文件累加
+        let mut sum = Breakdown::default();
+        sum.add(&b);
+        sum.add(&b);
+        assert_eq!(sum.total(), b.total() * 2);
+        assert!(breakdown(test_tokenizer(), Path::new("a.txt"), PYTHON, SpecialTokens::Allow).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::{create_dir_all, write};
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedChunk, ParsedParas},
+    token::{
+        FileToken,
+        SpecialTokens,
+        count,
+        file_len,
+        get_tokenizer,
+        size_limit_str,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+    traverse::text_files,
+};
+
+/// 切分chunk时优先使用的边界，找不到时依次尝试更细的边界，最后直接按token切分
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Boundary {
+    Paragraph, // 空行
+    Line,      // 换行
+    Sentence,  // 句号、问号、感叹号
+    Token,     // 直接按token切分
+}
+
+/// 一个chunk在原始字符串中的位置及token数
+#[derive(Debug)]
+pub struct Chunk {
+    pub start:  usize, // 起始byte位置
+    pub end:    usize, // 结束byte位置（不包含）
+    pub tokens: usize, // 该chunk单独计算的token数
+}
+
+/// 执行chunk子命令，将-f、-s、-p指定的文本切分为chunk，写入-o指定的路径或-j指定的JSONL文件，都没指定则JSONL输出到终端
+pub fn chunk_files(paras: &ParsedParas, chunk: &ParsedChunk) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    // 获取所有要切分的文本，(来源, -o输出时的相对路径, 文本)
+    let mut sources: Vec<(String, PathBuf, String)> = vec![];
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取的路径及错误状态
+    if let Some(files) = &paras.files {
+        for f in files {
+            let file_size = match file_len(f) {
+                Ok(s) => s,
+                Err(e) => { // 无法获取大小的文件
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            if file_size > paras.options.max_size {
+                eprintln!("[skip]: {} file size {} bytes > {}", f.display(), file_size, size_limit_str(paras.options.max_size));
+                continue
+            }
+            let file_token = match FileToken::new(f, file_size) {
+                Ok(t) => t,
+                Err(e) => { // 无法读取的文件
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            match file_token.with_encoding(paras.options.charset).with_binary(paras.options.binary).text_or_reason() {
+                Ok(text) => sources.push((f.display().to_string(), PathBuf::from(f.file_name().unwrap_or(f.as_os_str())), text)),
+                Err(reason) => eprintln!("[skip]: {} {}", f.display(), reason), // 例如`binary file: NUL byte at offset 0`
+            }
+        }
+    }
+    if let Some(s) = &paras.string {
+        sources.push(("-s string".to_string(), PathBuf::from("string"), s.clone()));
+    }
+    if let Some(p) = &paras.path {
+        for (rltv_path, text) in text_files(p, &paras.options, &mut skipped)? {
+            sources.push((rltv_path.display().to_string(), rltv_path, text));
+        }
+    }
+    for (path, status) in &skipped {
+        eprintln!("[skip]: {} {}", path.display(), status);
+    }
+    // 切分并输出
+    let mut lines: Vec<String> = vec![]; // JSONL的每行
+    for (source, rltv_path, text) in &sources {
+        let chunks = chunk_text(&bpe, text, chunk.max_tokens, chunk.overlap, chunk.boundary, paras.options.special);
+        for (i, c) in chunks.iter().enumerate() {
+            if c.tokens > chunk.max_tokens {
+                eprintln!("[warning]: {} chunk {}: a single character encodes to {} tokens > -n {}", source, i, c.tokens, chunk.max_tokens);
+            }
+            if let Some(outdir) = &chunk.outdir {
+                write_chunk(outdir, rltv_path, i, &text[c.start..c.end])?;
+            }
+            lines.push(json!({"source": source, "chunk": i, "start": c.start, "end": c.end, "tokens": c.tokens, "text": &text[c.start..c.end]}).to_string());
+        }
+        if chunk.outdir.is_some() || chunk.jsonl.is_some() {
+            println!("{} ({} tokens, {} chunks)", source, count(&bpe, text, paras.options.special), chunks.len());
+        }
+    }
+    if let Some(jsonl) = &chunk.jsonl {
+        let content = if lines.is_empty() { String::new() } else { lines.join("\n") + "\n" };
+        write(jsonl, content).map_err(|e| MyError::WriteFileError{file: jsonl.display().to_string(), error: e})?;
+    } else if chunk.outdir.is_none() {
+        for l in lines {
+            println!("{}", l);
+        }
+    }
+    Ok(())
+}
+
+/// 将第i个chunk写入`outdir/相对路径.i.txt`
+fn write_chunk(outdir: &Path, rltv_path: &Path, i: usize, text: &str) -> Result<(), MyError> {
+    let mut file = outdir.join(rltv_path).into_os_string();
+    file.push(format!(".{:04}.txt", i));
+    let file = PathBuf::from(file);
+    if let Some(dir) = file.parent() {
+        create_dir_all(dir).map_err(|e| MyError::CreateDirAllError{dir_name: dir.display().to_string(), error: e})?;
+    }
+    write(&file, text).map_err(|e| MyError::WriteFileError{file: file.display().to_string(), error: e})
+}
+
+/// 将字符串切分为token数不超过max_tokens的chunk，相邻chunk重叠overlap个token
+/// 每个chunk的结尾优先选在后半段最后一个boundary边界处，找不到则依次尝试更细的边界，最后直接按token切分，切分位置不会拆开多字节字符
+/// 单个字符的token数超过max_tokens时，该字符单独作为一个chunk，其tokens大于max_tokens
+pub fn chunk_text(bpe: &dyn Tokenizer, text: &str, max_tokens: usize, overlap: usize, boundary: Boundary, special: SpecialTokens) -> Vec<Chunk> {
+    let offsets = token_offsets(bpe, text, special);
+    let n = offsets.len() - 1; // 总token数
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut start = 0; // 当前chunk的起始token
+    while start < n {
+        let mut end = (start + max_tokens).min(n);
+        if end < n {
+            end = best_cut(text, &offsets, start, end, boundary);
+        }
+        // 切分后单独计算token数，可能与整体计算时不同，超过上限则往前回退
+        // 单个字符的token数就超过上限时无法再回退，该字符单独作为一个超过上限的chunk
+        let mut tokens = count(bpe, &text[offsets[start]..offsets[end]], special);
+        while tokens > max_tokens && end > start + 1 {
+            let shorter = char_cut(text, &offsets, start, end - 1);
+            if shorter >= end {
+                break
+            }
+            end = shorter;
+            tokens = count(bpe, &text[offsets[start]..offsets[end]], special);
+        }
+        chunks.push(Chunk{start: offsets[start], end: offsets[end], tokens});
+        if end >= n {
+            break
+        }
+        // 下一个chunk的起点，向后对齐到最近的边界及字符边界
+        let mut next = end.saturating_sub(overlap).max(start + 1);
+        if boundary != Boundary::Token && let Some(b) = (next..end).find(|&i| text.is_char_boundary(offsets[i]) && is_boundary(text, offsets[i], Boundary::Sentence)) {
+            next = b;
+        }
+        while next < n && !text.is_char_boundary(offsets[next]) {
+            next += 1;
+        }
+        start = next;
+    }
+    chunks
+}
+
+/// 在(start, end]后半段从后往前查找boundary边界，找不到则依次尝试更细的边界，都找不到则返回end（对齐到字符边界）
+fn best_cut(text: &str, offsets: &[usize], start: usize, end: usize, boundary: Boundary) -> usize {
+    let lowest = start + (end - start) / 2 + 1; // 只在后半段查找，避免chunk过小
+    for b in [Boundary::Paragraph, Boundary::Line, Boundary::Sentence] {
+        if b < boundary {
+            continue
+        }
+        if let Some(i) = (lowest..=end).rev().find(|&i| text.is_char_boundary(offsets[i]) && is_boundary(text, offsets[i], b)) {
+            return i
+        }
+    }
+    char_cut(text, offsets, start, end)
+}
+
+/// 从end往前找到第一个位于字符边界的token位置，(start, end]中都不在字符边界则往后找
+fn char_cut(text: &str, offsets: &[usize], start: usize, end: usize) -> usize {
+    match (start+1..=end).rev().find(|&i| text.is_char_boundary(offsets[i])) {
+        Some(i) => i,
+        None => (end..offsets.len()).find(|&i| text.is_char_boundary(offsets[i])).unwrap_or(offsets.len() - 1),
+    }
+}
+
+/// 判断byte位置pos是否是指定类型的边界，更粗的边界同时也是更细的边界（例如空行也是换行）
+fn is_boundary(text: &str, pos: usize, boundary: Boundary) -> bool {
+    let before = &text[..pos];
+    let after = &text[pos..];
+    match boundary {
+        Boundary::Paragraph => before.ends_with("\n\n") || before.ends_with("\n\r\n"),
+        Boundary::Line => before.ends_with('\n'),
+        Boundary::Sentence => before.ends_with('\n')
+            || before.ends_with(['。', '！', '？'])
+            || (before.ends_with(['.', '!', '?']) && after.starts_with(char::is_whitespace))
+            || (before.ends_with(char::is_whitespace) && before.trim_end().ends_with(['.', '!', '?'])),
+        Boundary::Token => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    /// 切分并返回每个chunk的文本和token数
+    fn chunks(text: &str, max_tokens: usize, overlap: usize, boundary: Boundary) -> Vec<(String, usize)> {
+        chunk_text(test_tokenizer(), text, max_tokens, overlap, boundary, SpecialTokens::Allow).iter().map(|c| (text[c.start..c.end].to_string(), c.tokens)).collect()
+    }
+
+    #[test]
+    fn multi_token_char_over_budget() {
+        let out = chunks("a 𝔘 b", 1, 0, Boundary::Token);
+        assert_eq!(out.iter().map(|(t, _)| t.as_str()).collect::<String>(), "a 𝔘 b");
+        let (_, tokens) = out.iter().find(|(t, _)| t == "𝔘").unwrap();
+        assert!(*tokens > 1);
+        assert!(out.iter().filter(|(t, _)| t != "𝔘").all(|(_, n)| *n <= 1));
+    }
+
+    #[test]
+    fn multi_token_chars_never_split() {
+        let text = "中文𝔘𝔙𝔚 emoji 👩‍👩‍👧‍👦 end";
+        for n in 1..6 {
+            let out = chunks(text, n, 0, Boundary::Paragraph);
+            assert_eq!(out.iter().map(|(t, _)| t.as_str()).collect::<String>(), text);
+        }
+    }
+
+    #[test]
+    fn overlap() {
+        let text = (0..40).map(|i| format!("w{}", i)).collect::<Vec<String>>().join(" ");
+        let out = chunk_text(test_tokenizer(), &text, 10, 3, Boundary::Token, SpecialTokens::Allow);
+        assert!(out.len() > 1);
+        for pair in out.windows(2) {
+            assert!(pair[1].start < pair[0].end); // 相邻chunk重叠
+            assert!(pair[1].start > pair[0].start); // 每次都向前推进
+        }
+        assert_eq!(out.last().unwrap().end, text.len());
+        assert!(out.iter().all(|c| c.tokens <= 10));
+    }
+
+    #[test]
+    fn paragraph_boundary() {
+        let out = chunks("one two three four five.\n\nsix seven eight nine ten eleven twelve", 10, 0, Boundary::Paragraph);
+        assert!(out[0].0.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn falls_back_to_line() {
+        let out = chunks("one two three four five\nsix seven eight nine ten eleven twelve", 10, 0, Boundary::Paragraph);
+        assert_eq!(out[0].0, "one two three four five\n");
+    }
+
+    #[test]
+    fn falls_back_to_sentence() {
+        let out = chunks("one two three four five. six seven eight nine ten eleven twelve", 10, 0, Boundary::Paragraph);
+        assert_eq!(out[0].0, "one two three four five.");
+    }
+
+    #[test]
+    fn falls_back_to_token() {
+        let out = chunks("one two three four five six seven eight nine ten eleven twelve", 10, 0, Boundary::Paragraph);
+        assert_eq!(out[0].1, 10);
+        assert_eq!(out.iter().map(|(t, _)| t.as_str()).collect::<String>(), "one two three four five six seven eight nine ten eleven twelve");
+    }
+}
@@ -0,0 +1,458 @@
+use std::path::{Path, PathBuf};
+
+use encoding_rs::Encoding;
+use termtree::Tree;
+
+use crate::{
+    archive::read_archive,
+    binary::BinaryClassifier,
+    breakdown::{Breakdown, breakdown},
+    error::MyError,
+    extract::{Extractor, ExtractorRegistry, Matcher},
+    image::{ImageDetail, image_token},
+    normalize::{Normalize, tokens_str},
+    notebook::notebook,
+    symbols::symbol_tree,
+    token::{
+        FileToken,
+        SpecialTokens,
+        count,
+        file_len,
+        find_special_tokens,
+        get_file_size,
+        size_limit_str,
+        special_tokens_str,
+    },
+    tokenizer::Tokenizer,
+    traverse::walk_directory,
+};
+
+/// 计算token的设置，命令行参数解析后也保存在这里，用`Options::default()`和builder方法逐项设置
+/// 以后可能增加新的设置，因此不能在crate外用结构体字面量创建
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Options {
+    pub max_size:         u64,                       // 文件大小上限，大小超过的文件不计算token，u64::MAX表示不限制，默认10Mb
+    pub min_token:        usize,                     // token数下限，token数小于该值的文件不会包含在结果中，默认0
+    pub max_token:        usize,                     // token数上限，token数大于该值的文件不会包含在结果中，默认usize::MAX
+    pub include:          Vec<String>,               // 遍历文件夹时只包含匹配这些glob的文件
+    pub exclude:          Vec<String>,               // 遍历文件夹时排除匹配这些glob的文件和文件夹
+    pub special:          SpecialTokens,             // 文本中特殊token的处理方式，默认allow
+    pub image_detail:     ImageDetail,               // 图片作为vision输入计算token的方式，默认high
+    pub binary:           BinaryClassifier,          // 二进制文件分类器，默认fast
+    pub charset:          Option<&'static Encoding>, // 指定文本文件的编码，None表示根据BOM或内容检测，也用于提取器解码HTML
+    pub normalize:        Normalize,                 // 计算token前对文本的规范化处理，默认不处理
+    pub archives:         bool,                      // 打开压缩包，计算其中每个文件的token数
+    pub extractors:       ExtractorRegistry,         // 文本提取器，默认没有
+    pub pages:            bool,                      // 在文件节点下显示提取文本的每页、每张幻灯片、每章的token数
+    pub notebook_outputs: bool,                      // .ipynb文件同时计算code cell的文本输出
+    pub symbols:          bool,                      // 在文件节点下显示每个函数、类、impl等的token数
+    pub breakdown:        bool,                      // 将源代码文件的token数分为代码、注释、字符串、空白
+    pub only_valid:       bool,                      // 结果中不包含二进制文件、大小超过上限的文件、空文件、含有无效字符的文件等
+    pub fail_fast:        bool,                      // 遇到无法读取的文件（例如没有权限）时立即返回错误，默认在结果中显示错误状态并继续
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options{
+            max_size: 10485760, // 10M=10*1024*1024=10485760
+            min_token: 0,
+            max_token: usize::MAX,
+            include: vec![],
+            exclude: vec![],
+            special: SpecialTokens::Allow,
+            image_detail: ImageDetail::High,
+            binary: BinaryClassifier::default(),
+            charset: None,
+            normalize: Normalize::default(),
+            archives: false,
+            extractors: ExtractorRegistry::default(),
+            pages: false,
+            notebook_outputs: false,
+            symbols: false,
+            breakdown: false,
+            only_valid: false,
+            fail_fast: false,
+        }
+    }
+}
+
+impl Options {
+    /// 文件大小上限，u64::MAX表示不限制
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// token数范围，只包含token数在[min, max]范围内的文件
+    pub fn tokens(mut self, min: usize, max: usize) -> Self {
+        (self.min_token, self.max_token) = (min, max);
+        self
+    }
+
+    /// 遍历文件夹时只包含匹配这些glob的文件
+    pub fn include(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.include = globs.into_iter().map(|g| g.into()).collect();
+        self
+    }
+
+    /// 遍历文件夹时排除匹配这些glob的文件和文件夹
+    pub fn exclude(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.exclude = globs.into_iter().map(|g| g.into()).collect();
+        self
+    }
+
+    /// 文本中特殊token的处理方式
+    pub fn special(mut self, special: SpecialTokens) -> Self {
+        self.special = special;
+        self
+    }
+
+    /// 图片作为vision输入计算token的方式
+    pub fn image_detail(mut self, detail: ImageDetail) -> Self {
+        self.image_detail = detail;
+        self
+    }
+
+    /// 二进制文件分类器
+    pub fn binary(mut self, classifier: BinaryClassifier) -> Self {
+        self.binary = classifier;
+        self
+    }
+
+    /// 指定文本文件的编码，计算时也用于提取器解码HTML，与extract()的调用顺序无关
+    pub fn charset(mut self, charset: &'static Encoding) -> Self {
+        self.charset = Some(charset);
+        self
+    }
+
+    /// 计算token前对文本的规范化处理
+    pub fn normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// 打开压缩包，计算其中每个文件的token数
+    pub fn archives(mut self, archives: bool) -> Self {
+        self.archives = archives;
+        self
+    }
+
+    /// 添加内置的文本提取器（HTML、DOCX、PPTX、ODT、ODP、EPUB、PDF），已注册的自定义提取器保留且优先
+    pub fn extract(mut self) -> Self {
+        self.extractors.register_builtin();
+        self
+    }
+
+    /// 注册自定义的文本提取器，后注册的优先
+    pub fn extractor(mut self, matcher: Matcher, extractor: impl Extractor + 'static) -> Self {
+        self.extractors.register(matcher, extractor);
+        self
+    }
+
+    /// 在文件节点下显示提取文本的每页、每张幻灯片、每章的token数
+    pub fn pages(mut self, pages: bool) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// .ipynb文件同时计算code cell的文本输出
+    pub fn notebook_outputs(mut self, outputs: bool) -> Self {
+        self.notebook_outputs = outputs;
+        self
+    }
+
+    /// 在文件节点下显示每个函数、类、impl等的token数
+    pub fn symbols(mut self, symbols: bool) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// 将源代码文件的token数分为代码、注释、字符串、空白
+    pub fn breakdown(mut self, breakdown: bool) -> Self {
+        self.breakdown = breakdown;
+        self
+    }
+
+    /// 结果中不包含二进制文件、大小超过上限的文件、空文件、含有无效字符的文件等
+    pub fn only_valid(mut self, only_valid: bool) -> Self {
+        self.only_valid = only_valid;
+        self
+    }
+
+    /// 遇到无法读取的文件时立即返回错误，否则在结果中显示错误状态并继续
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+}
+
+/// 单个文件的计算结果
+#[derive(Debug, Clone)]
+pub struct FileCount {
+    pub path:      PathBuf,           // 文件路径，count_dir中是以指定文件夹名开头的相对路径
+    pub size:      u64,               // 文件大小
+    pub tokens:    usize,             // token数，没有计算token的文件为0
+    pub valid:     bool,              // 是否计算了token数，二进制文件、大小超过上限、空文件、含有无效字符或不允许的特殊token的文件为false
+    pub label:     String,            // 显示在文件名后面括号中的描述，例如`1.20Kb, 300 tokens`
+    pub children:  Vec<Tree<String>>, // 文件节点的子节点，例如函数、cell、压缩包成员、PDF的页
+    pub raw:       Option<usize>,     // 指定了规范化时规范化前的token数
+    pub breakdown: Option<Breakdown>, // 源代码的代码、注释、字符串、空白token数
+    pub error:     Option<String>,    // 读取失败的原因，例如`permission denied`
+}
+
+impl FileCount {
+    /// 读取失败的文件，label是错误状态
+    pub fn failed(path: &Path, error: &MyError) -> Self {
+        let status = error.status();
+        FileCount{path: path.to_path_buf(), size: 0, tokens: 0, valid: false, label: status.clone(), children: vec![], raw: None, breakdown: None, error: Some(status)}
+    }
+
+    /// 是否包含在结果中：计算了token数的文件要在token数范围内，没有计算的文件在only_valid时不包含，读取失败的文件总是包含
+    pub fn kept(&self, options: &Options) -> bool {
+        if self.error.is_some() {
+            true
+        } else if self.valid {
+            self.tokens >= options.min_token && self.tokens <= options.max_token
+        } else {
+            !options.only_valid
+        }
+    }
+
+    /// 以指定名称作为根节点的tree，例如`main.rs (1.20Kb, 300 tokens)`
+    pub fn tree(&self, name: &str) -> Tree<String> {
+        Tree::new(format!("{} ({})", name, self.label)).with_leaves(self.children.clone())
+    }
+}
+
+/// 文件夹的计算结果
+#[derive(Debug)]
+pub struct DirCount {
+    pub tokens: usize,                  // 所有文件的总token数
+    pub size:   u64,                    // 所有文件的总大小
+    pub files:  Vec<FileCount>,         // 包含在结果中的文件，按遍历顺序
+    pub errors: Vec<(PathBuf, String)>, // 无法检查的路径及原因，包括读取失败的文件、没有权限的文件夹、断开的符号链接
+    pub tree:   String,                 // 命令行-p输出的tree
+}
+
+/// 计算token的入口，持有分词器和设置，可以在线程间共享
+/// ```
+/// # use std::path::Path;
+/// # use tokenum::{counter::{Counter, Options}, error::MyError, token::get_tokenizer};
+/// # fn main() -> Result<(), MyError> {
+/// let counter = Counter::new(get_tokenizer("o200k_base")?, Options::default().max_size(1 << 20).exclude(["target"]).symbols(true));
+/// let tokens = counter.count_str("hello world")?;
+/// assert_eq!(tokens, 2);
+/// let file = counter.count_file(Path::new("src/main.rs"))?;
+/// assert!(file.valid && file.kept(counter.options()));
+/// let dir = counter.count_dir(Path::new("src"))?;
+/// assert!(dir.files.iter().any(|f| f.path.ends_with("main.rs")));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Counter {
+    tokenizer: Box<dyn Tokenizer + Send + Sync>, // 分词器
+    options:   Options,                          // 设置
+}
+
+impl Counter {
+    pub fn new(tokenizer: impl Tokenizer + Send + Sync + 'static, options: Options) -> Self {
+        Counter{tokenizer: Box::new(tokenizer), options}
+    }
+
+    /// 分词器
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    /// 设置
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// 计算字符串的token数，指定了规范化时计算规范化后的token数，不允许特殊token时含有特殊token则返回错误
+    pub fn count_str(&self, text: &str) -> Result<usize, MyError> {
+        let found = find_special_tokens(self.tokenizer(), text);
+        if self.options.special == SpecialTokens::Disallow && !found.is_empty() {
+            return Err(MyError::SpecialTokensError{name: "string".to_string(), found: special_tokens_str(&found)})
+        }
+        if self.options.normalize.is_empty() {
+            Ok(count(self.tokenizer(), text, self.options.special))
+        } else {
+            Ok(count(self.tokenizer(), &self.options.normalize.apply(text), self.options.special))
+        }
+    }
+
+    /// 计算单个文件的token数，是否包含在结果中用FileCount::kept判断，读取失败返回错误
+    pub fn count_file(&self, path: &Path) -> Result<FileCount, MyError> {
+        file_count(self.tokenizer(), path, &self.options)
+    }
+
+    /// 递归计算文件夹下所有文件的token数，遵循.gitignore和include、exclude，无法读取的文件在fail_fast时返回错误，否则作为错误节点
+    pub fn count_dir(&self, path: &Path) -> Result<DirCount, MyError> {
+        walk_directory(path, self.tokenizer(), &self.options)
+    }
+}
+
+/// 读取单个文件失败时，fail_fast返回错误，否则将路径和错误状态加入skipped并继续，由调用者决定如何报告
+pub fn skip_or_fail(path: &Path, error: MyError, options: &Options, skipped: &mut Vec<(PathBuf, String)>) -> Result<(), MyError> {
+    if options.fail_fast {
+        return Err(error)
+    }
+    skipped.push((path.to_path_buf(), error.status()));
+    Ok(())
+}
+
+/// 计算单个文件的token数，依次按压缩包、提取器、图片、文本、二进制文件处理，获取大小或读取失败返回错误
+pub fn file_count(bpe: &dyn Tokenizer, path: &Path, options: &Options) -> Result<FileCount, MyError> {
+    let size = file_len(path)?;
+    let mut out = FileCount{path: path.to_path_buf(), size, tokens: 0, valid: false, label: String::new(), children: vec![], raw: None, breakdown: None, error: None};
+    if size > options.max_size {
+        out.label = format!("{}, file size {} bytes > {}", get_file_size(size), size, size_limit_str(options.max_size));
+        return Ok(out)
+    }
+    let file_token = FileToken::new(path, size)?.with_encoding(options.charset).with_binary(options.binary);
+    if options.archives && let Some(archive) = read_archive(bpe, path, file_token.raw(), options) {
+        // 压缩包中每个文件的token数，作为压缩包节点的子节点
+        match archive {
+            Ok(a) => {
+                out.label = format!("{}, {}", file_token.size, a.label());
+                (out.tokens, out.valid, out.children) = (a.tokens, true, a.tree);
+            },
+            Err(e) => out.label = format!("{}, invalid archive: {}", file_token.size, e),
+        }
+    } else {
+        content_count(bpe, path, file_token, options, &mut out);
+    }
+    Ok(out)
+}
+
+/// 按提取器、图片、文本、二进制文件对已读取的内容分类并计算token数，结果写入out，文件和压缩包成员共用
+/// path用于按后缀匹配提取器、notebook、symbol，压缩包成员是在压缩包中的路径
+pub(crate) fn content_count(bpe: &dyn Tokenizer, path: &Path, mut file_token: FileToken, options: &Options, out: &mut FileCount) {
+    if let Some(extracted) = options.extractors.extract(bpe, path, file_token.raw(), options) {
+        // 注册的提取器从文件中提取的文本，例如HTML的可见文本，文档中的文本
+        match extracted {
+            Ok(e) => {
+                out.label = format!("{}, {}", file_token.size, e.label());
+                (out.tokens, out.valid, out.children) = (e.tokens, true, e.segments);
+            },
+            Err(e) => out.label = format!("{}, {}", file_token.size, e),
+        }
+    } else if let Some((width, height)) = file_token.image_size() {
+        out.tokens = image_token(width, height, options.image_detail); // 图片作为vision输入的token数
+        out.valid = true;
+        out.label = format!("{}, {}x{} image, {} tokens", file_token.size, width, height, out.tokens);
+    } else if file_token.not_binary() {
+        let found = if options.special == SpecialTokens::Disallow { find_special_tokens(bpe, &file_token.string) } else { vec![] };
+        if file_token.string.is_empty() {
+            out.label = format!("{}, 0 token", file_token.size_encoding());
+        } else if file_token.string.contains(char::REPLACEMENT_CHARACTER) {
+            // 含有无效字符，REPLACEMENT_CHARACTER表示无效字符“�”
+            out.label = format!("{}, contain invalid {}", file_token.size_encoding(), file_token.encoding_name());
+        } else if !found.is_empty() {
+            // 不允许特殊token时，含有特殊token的文件不计算token数，并列出特殊token的位置
+            out.label = format!("{}, contain special tokens: {}", file_token.size_encoding(), special_tokens_str(&found));
+        } else {
+            match notebook(bpe, path, &file_token.string, options.notebook_outputs, options.special) {
+                Some(Ok(nb)) => { // Jupyter notebook按cell计算
+                    out.label = format!("{}, {}", file_token.size_encoding(), nb.label());
+                    (out.tokens, out.valid, out.children) = (nb.tokens, true, nb.cells);
+                },
+                fallback => {
+                    out.raw = file_token.normalize(bpe, &options.normalize, options.special); // 规范化前的token数
+                    out.tokens = count(bpe, &file_token.string, options.special);
+                    out.valid = true;
+                    if options.symbols { // 在文件下显示每个函数、类等的token数
+                        out.children = symbol_tree(bpe, path, &file_token.string, options.special);
+                    }
+                    if let Some(Err(reason)) = fallback { // 不是有效的notebook，按原始JSON计算，原因作为子节点
+                        out.children.push(Tree::new(format!("not a valid notebook, counted as raw JSON: {}", reason)));
+                    }
+                    out.breakdown = options.breakdown.then(|| breakdown(bpe, path, &file_token.string, options.special)).flatten();
+                    out.label = match &out.breakdown {
+                        Some(b) => format!("{}, {}, {}", file_token.size_encoding(), tokens_str(out.raw, out.tokens), b.columns()), // 代码、注释、字符串、空白的token数
+                        None => format!("{}, {}", file_token.size_encoding(), tokens_str(out.raw, out.tokens)),
+                    };
+                },
+            }
+        }
+    } else {
+        out.label = format!("{}, binary file: {}", file_token.size, file_token.binary.unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use encoding_rs::GBK;
+
+    use super::*;
+    use crate::{
+        extract::TextSegments,
+        token::get_tokenizer,
+    };
+
+    /// 在临时文件夹中创建文件，返回文件夹路径
+    fn temp_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tokenum-counter-{}-{}", name, std::process::id()));
+        create_dir_all(&dir).unwrap();
+        for (f, content) in files {
+            write(dir.join(f), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Options>();
+        assert_send_sync::<Counter>();
+    }
+
+    #[test]
+    fn charset_order_independent() {
+        let html = GBK.encode("<html><body><p>中文内容</p></body></html>").0.into_owned();
+        let dir = temp_dir("charset", &[("gbk.html", &html)]);
+        let file = dir.join("gbk.html");
+        let before = Counter::new(get_tokenizer("o200k_base").unwrap(), Options::default().charset(GBK).extract());
+        let after = Counter::new(get_tokenizer("o200k_base").unwrap(), Options::default().extract().charset(GBK));
+        let (before, after) = (before.count_file(&file).unwrap(), after.count_file(&file).unwrap());
+        assert!(before.valid && before.label.contains("extracted HTML text"));
+        assert_eq!((before.tokens, &before.label), (after.tokens, &after.label));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    /// 把整个文件作为一段文本的HTML提取器
+    struct RawHtml;
+
+    impl Extractor for RawHtml {
+        fn format(&self) -> &str {
+            "raw HTML"
+        }
+
+        fn extract(&self, _: &Path, raw: &[u8]) -> Result<TextSegments, String> {
+            Ok(TextSegments{markup: None, segments: vec![String::from_utf8_lossy(raw).to_string()]})
+        }
+    }
+
+    #[test]
+    fn extract_keeps_custom_extractors() {
+        let dir = temp_dir("custom", &[("a.html", b"<p>hi</p>")]);
+        let options = Options::default().extractor(Matcher::Extension("html".to_string()), RawHtml).extract().extract();
+        let counter = Counter::new(get_tokenizer("o200k_base").unwrap(), options);
+        assert!(counter.count_file(&dir.join("a.html")).unwrap().label.contains("extracted raw HTML text"));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_or_fail_collects() {
+        let error = || MyError::WalkError{path: "x".to_string(), error: "permission denied".to_string()};
+        let mut skipped = vec![];
+        skip_or_fail(Path::new("x"), error(), &Options::default(), &mut skipped).unwrap();
+        assert_eq!(skipped, vec![(PathBuf::from("x"), "permission denied".to_string())]);
+        assert!(skip_or_fail(Path::new("x"), error(), &Options::default().fail_fast(true), &mut skipped).is_err());
+        assert_eq!(skipped.len(), 1);
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{read_to_string, stdin};
+
+use crate::{
+    error::MyError,
+    parse_paras::{ParsedDecode, ParsedParas},
+    token::get_tokenizer,
+    tokenizer::{Rank, Tokenizer},
+};
+
+/// 执行decode子命令，将token ID解码为文本，没有指定ID则从标准输入读取
+/// 无法解析的ID、不在词表中的ID、不完整或无效的UTF-8序列会在标准错误中逐个报告，不会中断解码
+pub fn decode_input(paras: &ParsedParas, decode: &ParsedDecode) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let input = match &decode.ids {
+        Some(ids) => ids.clone(),
+        None => read_to_string(stdin())?,
+    };
+    let (text, warnings) = decode_ids(&bpe, &parse_ids(&input)?);
+    println!("{}", text);
+    for w in warnings {
+        eprintln!("[warning]: {}", w);
+    }
+    Ok(())
+}
+
+/// 解析token ID列表，支持JSON数组（例如`[791, 11564]`）或逗号、空白间隔的ID
+/// 返回每个ID的原始字符串及解析结果，无法解析为u32的ID为None
+pub fn parse_ids(input: &str) -> Result<Vec<(String, Option<Rank>)>, MyError> {
+    let input = input.trim();
+    if input.starts_with('[') {
+        let values: Vec<serde_json::Value> = serde_json::from_str(input).map_err(|e| MyError::ParseJsonError{file: "token ID array".to_string(), error: e})?;
+        Ok(values.into_iter().map(|v| {
+            let id = v.as_u64().and_then(|i| Rank::try_from(i).ok());
+            (v.to_string(), id)
+        }).collect())
+    } else {
+        Ok(input.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()).map(|s| (s.to_string(), s.parse::<Rank>().ok())).collect())
+    }
+}
+
+/// 将token ID解码为文本，返回(解码后的文本, 警告信息)
+/// 跳过无法解析和不在词表中的ID，无效的UTF-8序列以`�`代替
+pub fn decode_ids(bpe: &dyn Tokenizer, ids: &[(String, Option<Rank>)]) -> (String, Vec<String>) {
+    let mut warnings: Vec<String> = vec![];
+    let mut bytes: Vec<u8> = vec![];
+    let mut pending: Vec<u8> = vec![]; // 还不能组成完整字符的byte
+    let mut pending_from = 0; // pending中第一个byte所在的token序号
+    for (i, (raw, id)) in ids.iter().enumerate() {
+        let token_bytes = match id {
+            Some(id) => match bpe.token_bytes(*id) {
+                Some(b) => b,
+                None => {
+                    warnings.push(format!("token #{}: id {} is not in {}", i, id, bpe.name()));
+                    continue
+                },
+            },
+            None => {
+                warnings.push(format!("token #{}: {} is not a valid token id", i, raw));
+                continue
+            },
+        };
+        bytes.extend(&token_bytes);
+        if pending.is_empty() {
+            pending_from = i;
+        }
+        pending.extend(&token_bytes);
+        match std::str::from_utf8(&pending) {
+            Ok(_) => pending.clear(),
+            Err(e) if e.error_len().is_none() => (), // 多字节字符还不完整，等待后面的token
+            Err(_) if pending_from < i => { // 前面不完整的多字节字符被当前token打断，当前token重新检查
+                warnings.push(format!("incomplete UTF-8 sequence at {}", token_range(pending_from, i - 1)));
+                pending = token_bytes;
+                pending_from = i;
+                match std::str::from_utf8(&pending) {
+                    Ok(_) => pending.clear(),
+                    Err(e) if e.error_len().is_none() => (),
+                    Err(_) => {
+                        warnings.push(format!("invalid UTF-8 sequence at {}", token_range(i, i)));
+                        pending.clear();
+                    },
+                }
+            },
+            Err(_) => {
+                warnings.push(format!("invalid UTF-8 sequence at {}", token_range(i, i)));
+                pending.clear();
+            },
+        }
+    }
+    if !pending.is_empty() {
+        warnings.push(format!("incomplete UTF-8 sequence at {}", token_range(pending_from, ids.len() - 1)));
+    }
+    (String::from_utf8_lossy(&bytes).to_string(), warnings)
+}
+
+/// token序号范围的描述，例如`token #3`、`tokens #0..#2`
+fn token_range(from: usize, to: usize) -> String {
+    if from == to {
+        format!("token #{}", from)
+    } else {
+        format!("tokens #{}..#{}", from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        token::{SpecialTokens, encode},
+        tokenizer::test_tokenizer,
+    };
+
+    /// 将文本编码为decode_ids的输入
+    fn ids(text: &str) -> Vec<(String, Option<Rank>)> {
+        encode(test_tokenizer(), text, SpecialTokens::Allow).into_iter().map(|i| (i.to_string(), Some(i))).collect()
+    }
+
+    #[test]
+    fn parse_json_array() {
+        assert_eq!(parse_ids(" [791, 11564] ").unwrap(), vec![("791".to_string(), Some(791)), ("11564".to_string(), Some(11564))]);
+        assert_eq!(parse_ids("[1, -2, \"x\", 4294967296]").unwrap().iter().map(|(_, id)| *id).collect::<Vec<_>>(), vec![Some(1), None, None, None]);
+        assert!(parse_ids("[1, 2").is_err());
+    }
+
+    #[test]
+    fn parse_separated() {
+        assert_eq!(parse_ids("1,2 3\n\t4,,").unwrap().iter().map(|(_, id)| *id).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(parse_ids("12 abc -1").unwrap(), vec![("12".to_string(), Some(12)), ("abc".to_string(), None), ("-1".to_string(), None)]);
+        assert!(parse_ids("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "hello 世界 𝔘 👩‍👩‍👧";
+        let (out, warnings) = decode_ids(test_tokenizer(), &ids(text));
+        assert_eq!(out, text);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn invalid_id_skipped() {
+        let mut input = ids("hello");
+        input.insert(0, ("abc".to_string(), None));
+        let (out, warnings) = decode_ids(test_tokenizer(), &input);
+        assert_eq!(out, "hello");
+        assert_eq!(warnings, vec!["token #0: abc is not a valid token id".to_string()]);
+    }
+
+    #[test]
+    fn unknown_id_skipped() {
+        let mut input = ids("hello");
+        input.push(("4000000000".to_string(), Some(4000000000)));
+        let (out, warnings) = decode_ids(test_tokenizer(), &input);
+        assert_eq!(out, "hello");
+        assert_eq!(warnings, vec![format!("token #{}: id 4000000000 is not in o200k_base", input.len() - 1)]);
+    }
+
+    #[test]
+    fn incomplete_utf8() {
+        let char_ids = ids("𝔘");
+        assert!(char_ids.len() > 1); // 一个字符被编码为多个token
+        // 结尾不完整
+        let (out, warnings) = decode_ids(test_tokenizer(), &char_ids[..char_ids.len() - 1]);
+        assert_eq!(out, "\u{FFFD}");
+        assert_eq!(warnings, vec![format!("incomplete UTF-8 sequence at {}", token_range(0, char_ids.len() - 2))]);
+        // 被后面完整的token打断
+        let mut input = char_ids[..char_ids.len() - 1].to_vec();
+        input.extend(ids("ok"));
+        let (out, warnings) = decode_ids(test_tokenizer(), &input);
+        assert_eq!(out, "\u{FFFD}ok");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("incomplete UTF-8 sequence"));
+    }
+}
@@ -1,5 +1,6 @@
 use std::io;
 use std::num::ParseIntError;
+use std::path::Path;
 use std::string::FromUtf8Error;
 
 use thiserror::Error;
@@ -16,6 +17,10 @@ pub enum MyError {
     #[error("Error - fs::read {file}: {error}")]
     ReadFileError{file: String, error: io::Error},
 
+    // 获取文件信息错误
+    #[error("Error - fs::metadata {file}: {error}")]
+    MetadataError{file: String, error: io::Error},
+
     // 打开文件错误
     #[error("Error - fs::File::open {file}: {error}")]
     OpenFileError{file: String, error: io::Error},
@@ -68,10 +73,38 @@ pub enum MyError {
     #[error("Error - {file} to UTF-8: {error}")]
     FileContentToUtf8Error{file: String, error: FromUtf8Error},
 
+    // 解析JSON错误
+    #[error("Error - parse JSON {file}: {error}")]
+    ParseJsonError{file: String, error: serde_json::Error},
+
+    // 解析YAML错误
+    #[error("Error - parse YAML {file}: {error}")]
+    ParseYamlError{file: String, error: serde_yaml::Error},
+
+    // glob格式错误
+    #[error("Error - glob {glob}: {error}")]
+    GlobError{glob: String, error: globset::Error},
+
     // Tokenizer错误
     #[error("Error - Initialize {tokenizer} tokenizer: {error}")]
     TokenizerError{tokenizer: String, error: anyhow::Error},
 
+    // 不允许特殊token时含有特殊token
+    #[error("Error - {name} contain special tokens: {found}")]
+    SpecialTokensError{name: String, found: String},
+
+    // 不是文本文件，例如二进制文件、含有无效字符的文件
+    #[error("Error - {file} is not text: {reason}")]
+    NotTextError{file: String, reason: String},
+
+    // 遍历路径时无法检查的项，例如没有权限的文件夹
+    #[error("Error - walk {path}: {error}")]
+    WalkError{path: String, error: String},
+
+    // --strict时有无法检查的路径
+    #[error("Error - {count} paths could not be examined")]
+    StrictError{count: usize},
+
     // 参数使用错误
     #[error("Error - {para}")]
     ParaError{para: String},
@@ -80,3 +113,38 @@ pub enum MyError {
     #[error("I/O error occurred")]
     IoError(#[from] io::Error),
 }
+
+impl MyError {
+    /// 显示在tree中文件名后面的错误状态，例如`permission denied`、`symlink loop`、`read error: ...`
+    pub fn status(&self) -> String {
+        match self {
+            MyError::MetadataError{file, error} if error.kind() == io::ErrorKind::NotFound && Path::new(file).is_symlink() => "broken symlink".to_string(),
+            MyError::ReadFileError{error, ..} | MyError::MetadataError{error, ..} | MyError::ReadDirError{error, ..} | MyError::IoError(error) => io_status(error),
+            MyError::WalkError{error, ..} => error.clone(),
+            MyError::NotTextError{reason, ..} => reason.clone(),
+            MyError::ParseJsonError{error, ..} => format!("invalid JSON: {}", error),
+            MyError::ParseYamlError{error, ..} => format!("invalid YAML: {}", error),
+            e => e.to_string(),
+        }
+    }
+}
+
+/// 符号链接循环（ELOOP）的系统错误码，io::ErrorKind::FilesystemLoop还不稳定，只能按错误码判断
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))]
+const ELOOP: i32 = 62;
+#[cfg(windows)]
+const ELOOP: i32 = 1921; // ERROR_CANT_RESOLVE_FILENAME
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly", windows)))]
+const ELOOP: i32 = 40;
+
+/// io::Error对应的错误状态
+pub fn io_status(error: &io::Error) -> String {
+    if error.raw_os_error() == Some(ELOOP) {
+        return "symlink loop".to_string()
+    }
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        io::ErrorKind::NotFound => "not found".to_string(),
+        _ => format!("read error: {}", error),
+    }
+}
@@ -0,0 +1,595 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::panic::catch_unwind;
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8};
+use roxmltree::{Document, Node};
+use scraper::{ElementRef, Html};
+use termtree::Tree;
+use zip::ZipArchive;
+
+use crate::{
+    counter::Options,
+    token::{
+        SpecialTokens,
+        count,
+        find_special_tokens,
+        size_limit_str,
+        special_tokens_str,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 不显示的HTML元素，其中的文本不提取
+const HIDDEN_ELEMENTS: [&str; 9] = ["head", "script", "style", "noscript", "template", "svg", "math", "iframe", "object"];
+
+thread_local! {
+    /// 当前线程是否正在调用会捕获panic的第三方提取器（例如pdf-extract）
+    static EXTRACTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 当前线程是否正在调用会捕获panic的第三方提取器，这时的panic会作为错误信息返回
+/// 程序可以在自己的panic hook中据此不打印这些panic信息，库本身不修改panic hook
+pub fn is_extracting() -> bool {
+    EXTRACTING.with(Cell::get)
+}
+
+/// ODF中`text:s`的c属性来自文件，限制展开的空格数，避免构造的文件（例如`c="4000000000"`）耗尽内存
+const MAX_ODF_SPACES: usize = 1024;
+
+/// 块级HTML元素，前后换行
+const BLOCK_ELEMENTS: [&str; 35] = [
+    "address", "article", "aside", "blockquote", "caption", "dd", "details", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer",
+    "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tr", "ul", "body",
+];
+
+/// 提取器从文件中提取出的文本
+pub struct TextSegments {
+    pub markup:   Option<String>, // 原始标记文本（例如HTML、容器中的XML），用于显示提取节省的token数，没有则为None
+    pub segments: Vec<String>,    // 每段文本，例如PDF的每页，合并时段之间空一行
+}
+
+/// 文本提取器，将文件内容转为若干段文本，实现该trait并注册到ExtractorRegistry即可计算自定义格式的token数
+/// 需要Send + Sync，注册表可以随Options在线程间共享
+pub trait Extractor: Send + Sync {
+    /// 格式名称，显示在tree中，例如HTML、PDF
+    fn format(&self) -> &str;
+
+    /// 每段文本的名称，例如page、slide，--pages时在文件节点下显示每段的token数，None表示不显示
+    fn segment(&self) -> Option<&str> {
+        None
+    }
+
+    /// 提取文本，失败时返回的错误信息显示为`invalid {format}: {error}`
+    fn extract(&self, file: &Path, raw: &[u8]) -> Result<TextSegments, String>;
+
+    /// 按计算时Options中指定的文本编码提取文本，None表示没有指定，默认忽略编码直接调用extract
+    /// 需要解码文本的格式（例如HTML）可以实现该方法，使用--input-encoding
+    fn extract_with_charset(&self, file: &Path, raw: &[u8], charset: Option<&'static Encoding>) -> Result<TextSegments, String> {
+        let _ = charset;
+        self.extract(file, raw)
+    }
+
+    /// 同extract_with_charset，max_size是Options中的文件大小上限，用于限制从容器（例如zip）中解压的单个文件，超过则返回错误
+    /// 默认忽略该上限，内置的DOCX、PPTX、ODT、ODP、EPUB提取器实现了该方法
+    fn extract_with_limit(&self, file: &Path, raw: &[u8], charset: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+        let _ = max_size;
+        self.extract_with_charset(file, raw, charset)
+    }
+}
+
+/// 根据文件路径和内容判断是否由提取器处理
+pub type Predicate = Box<dyn Fn(&Path, &[u8]) -> bool + Send + Sync>;
+
+/// 选择提取器的方式
+pub enum Matcher {
+    Extension(String),                    // 文件名后缀，不区分大小写，可以包含`.`，例如`pb.bin`
+    Magic{offset: usize, bytes: Vec<u8>}, // 文件内容在offset处的标识
+    Predicate(Predicate),                 // 根据路径和内容判断
+}
+
+impl Matcher {
+    /// 文件是否由该提取器处理
+    pub fn matches(&self, file: &Path, raw: &[u8]) -> bool {
+        match self {
+            Matcher::Extension(e) => file.file_name().is_some_and(|n| n.to_string_lossy().to_lowercase().ends_with(&format!(".{}", e.to_lowercase()))),
+            Matcher::Magic{offset, bytes} => raw.get(*offset..offset + bytes.len()) == Some(bytes.as_slice()),
+            Matcher::Predicate(p) => p(file, raw),
+        }
+    }
+}
+
+/// 文本提取器的注册表，calculate_token和traverse_directory先在此查找文件的提取器，找不到再按图片、文本、二进制文件处理
+#[derive(Default)]
+pub struct ExtractorRegistry {
+    extractors: Vec<(Matcher, Box<dyn Extractor>)>, // 按注册顺序，后注册的优先
+    builtin:    bool,                               // 是否已添加内置提取器
+}
+
+impl fmt::Debug for ExtractorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.extractors.iter().map(|(_, e)| e.format())).finish()
+    }
+}
+
+impl ExtractorRegistry {
+    /// 包含内置提取器的注册表：HTML的可见文本，DOCX、PPTX、ODT、ODP、EPUB、PDF中的文本
+    /// HTML在计算时按Options中指定的编码解码，没有指定则按UTF-8或BOM
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register_builtin();
+        registry
+    }
+
+    /// 添加内置提取器，放在已注册的提取器之前，因此已注册的自定义提取器仍然优先，已添加过则不重复添加
+    pub fn register_builtin(&mut self) -> &mut Self {
+        if !self.builtin {
+            let mut builtins: Vec<(Matcher, Box<dyn Extractor>)> = BUILTINS.iter().map(|&(ext, format, segment, extract)| (Matcher::Extension(ext.to_string()), Box::new(Builtin{format, segment, extract}) as Box<dyn Extractor>)).collect();
+            builtins.push((Matcher::Magic{offset: 0, bytes: b"%PDF-".to_vec()}, Box::new(Builtin{format: "PDF", segment: Some("page"), extract: pdf}))); // 没有`.pdf`后缀的PDF
+            self.extractors.splice(0..0, builtins);
+            self.builtin = true;
+        }
+        self
+    }
+
+    /// 注册提取器，后注册的优先，因此可以覆盖内置提取器
+    pub fn register(&mut self, matcher: Matcher, extractor: impl Extractor + 'static) -> &mut Self {
+        self.extractors.push((matcher, Box::new(extractor)));
+        self
+    }
+
+    /// 是否没有注册任何提取器
+    pub fn is_empty(&self) -> bool {
+        self.extractors.is_empty()
+    }
+
+    /// 查找处理该文件的提取器
+    pub fn find(&self, file: &Path, raw: &[u8]) -> Option<&dyn Extractor> {
+        self.extractors.iter().rev().find(|(m, _)| m.matches(file, raw)).map(|(_, e)| e.as_ref())
+    }
+
+    /// 用找到的提取器提取文本并计算token数，没有匹配的提取器返回None，按options中的文本编码、文件大小上限提取
+    /// 提取失败或提取的文本含有不允许的特殊token则返回错误信息，options.pages为true时计算每段的token数
+    pub fn extract(&self, bpe: &dyn Tokenizer, file: &Path, raw: &[u8], options: &Options) -> Option<Result<Extracted, String>> {
+        let (pages, special) = (options.pages, options.special);
+        let extractor = self.find(file, raw)?;
+        let format = extractor.format().to_string();
+        Some(extractor.extract_with_limit(file, raw, options.charset, options.max_size).map_err(|e| format!("invalid {}: {}", format, e)).and_then(|TextSegments{markup, segments: texts}| {
+            let text = texts.join("\n\n"); // 段之间空一行
+            let found = find_special_tokens(bpe, &text);
+            if special == SpecialTokens::Disallow && !found.is_empty() {
+                return Err(format!("contain special tokens: {}", special_tokens_str(&found)))
+            }
+            let segments = match extractor.segment() {
+                Some(name) if pages => texts.iter().enumerate().map(|(i, t)| {
+                    let tokens = count(bpe, t, special);
+                    Tree::new(if tokens == 0 { format!("{} {} (0 token)", name, i + 1) } else { format!("{} {} ({} tokens)", name, i + 1, tokens) })
+                }).collect(),
+                _ => vec![],
+            };
+            Ok(Extracted{
+                tokens: count(bpe, &text, special),
+                raw: markup.map(|m| count(bpe, &m, special)),
+                count: texts.len(),
+                segment: extractor.segment().map(|s| s.to_string()),
+                format,
+                segments,
+            })
+        }))
+    }
+}
+
+/// 内置提取器的提取函数，charset只用于HTML，max_size只用于zip容器中的文件
+type ExtractFn = fn(&[u8], Option<&'static Encoding>, u64) -> Result<TextSegments, String>;
+
+/// 内置的格式，(后缀, 格式名称, 每段文本的名称, 提取函数)
+const BUILTINS: [(&str, &str, Option<&str>, ExtractFn); 9] = [
+    ("html", "HTML", None, html),
+    ("htm", "HTML", None, html),
+    ("xhtml", "HTML", None, html),
+    ("docx", "DOCX", None, docx),
+    ("pptx", "PPTX", Some("slide"), pptx),
+    ("odt", "ODT", None, odf),
+    ("odp", "ODP", None, odf),
+    ("epub", "EPUB", Some("chapter"), epub),
+    ("pdf", "PDF", Some("page"), pdf),
+];
+
+/// 内置提取器
+struct Builtin {
+    format:  &'static str,         // 格式名称
+    segment: Option<&'static str>, // 每段文本的名称
+    extract: ExtractFn,            // 提取函数
+}
+
+impl Extractor for Builtin {
+    fn format(&self) -> &str {
+        self.format
+    }
+
+    fn segment(&self) -> Option<&str> {
+        self.segment
+    }
+
+    fn extract(&self, _: &Path, raw: &[u8]) -> Result<TextSegments, String> {
+        (self.extract)(raw, None, u64::MAX)
+    }
+
+    fn extract_with_charset(&self, _: &Path, raw: &[u8], charset: Option<&'static Encoding>) -> Result<TextSegments, String> {
+        (self.extract)(raw, charset, u64::MAX)
+    }
+
+    fn extract_with_limit(&self, _: &Path, raw: &[u8], charset: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+        (self.extract)(raw, charset, max_size)
+    }
+}
+
+/// 从文件中提取的文本的token数
+pub struct Extracted {
+    pub format:   String,            // 格式名称，例如HTML、DOCX、PDF
+    pub tokens:   usize,             // 提取出的文本的token数
+    pub raw:      Option<usize>,     // 原始标记文本的token数，PDF没有
+    pub count:    usize,             // 文本的段数，例如PDF的页数
+    pub segment:  Option<String>,    // 每段文本的名称，例如page
+    pub segments: Vec<Tree<String>>, // --pages时每页、每张幻灯片、每章的token数，作为文件节点的子节点
+}
+
+impl Extracted {
+    /// 显示在文件大小后面的描述
+    pub fn label(&self) -> String {
+        match self.raw {
+            Some(raw) => {
+                let saves = if raw > self.tokens {
+                    format!("saves {} tokens ({:.1}%)", raw - self.tokens, (raw - self.tokens) as f64 * 100.0 / raw as f64)
+                } else {
+                    "saves 0 token".to_string()
+                };
+                format!("extracted {} text {} tokens, raw markup {} tokens, {}", self.format, self.tokens, raw, saves)
+            },
+            None => match &self.segment {
+                Some(s) => format!("extracted {} text {} tokens, {} {}s", self.format, self.tokens, self.count, s),
+                None => format!("extracted {} text {} tokens", self.format, self.tokens),
+            },
+        }
+    }
+}
+
+/// HTML按指定编码（默认UTF-8，有BOM则按BOM）解码后提取可见文本
+fn html(raw: &[u8], charset: Option<&'static Encoding>, _: u64) -> Result<TextSegments, String> {
+    let (markup, _, _) = charset.unwrap_or(UTF_8).decode(raw);
+    let text = html_text(&markup);
+    Ok(TextSegments{markup: Some(markup.into_owned()), segments: vec![text]})
+}
+
+/// DOCX的正文在`word/document.xml`
+fn docx(raw: &[u8], _: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+    let mut zip = ZipArchive::new(Cursor::new(raw)).map_err(|e| e.to_string())?;
+    let xml = zip_entry(&mut zip, "word/document.xml", max_size)?;
+    let text = ooxml_text(&xml)?;
+    Ok(TextSegments{markup: Some(xml), segments: vec![text]})
+}
+
+/// PPTX每张幻灯片在`ppt/slides/slide{n}.xml`，按编号顺序提取
+fn pptx(raw: &[u8], _: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+    let mut zip = ZipArchive::new(Cursor::new(raw)).map_err(|e| e.to_string())?;
+    let mut slides: Vec<(usize, String)> = zip.file_names()
+        .filter_map(|n| n.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse().ok().map(|i| (i, n.to_string())))
+        .collect();
+    if slides.is_empty() {
+        return Err("no slides in ppt/slides/".to_string())
+    }
+    slides.sort();
+    let (mut markup, mut texts) = (vec![], vec![]);
+    for (_, name) in slides {
+        let xml = zip_entry(&mut zip, &name, max_size)?;
+        texts.push(ooxml_text(&xml)?);
+        markup.push(xml);
+    }
+    Ok(TextSegments{markup: Some(markup.join("\n")), segments: texts})
+}
+
+/// ODT、ODP的正文在`content.xml`
+fn odf(raw: &[u8], _: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+    let mut zip = ZipArchive::new(Cursor::new(raw)).map_err(|e| e.to_string())?;
+    let xml = zip_entry(&mut zip, "content.xml", max_size)?;
+    let doc = Document::parse(&xml).map_err(|e| format!("content.xml: {}", e))?;
+    let body = doc.descendants().find(|n| n.has_tag_name("body")).ok_or("content.xml: no office:body")?;
+    let mut text = String::new();
+    odf_walk(body, false, &mut text);
+    Ok(TextSegments{markup: Some(xml), segments: vec![tidy(&text)]})
+}
+
+/// EPUB按`META-INF/container.xml`指定的OPF文件中spine的顺序提取每个XHTML文件（每章）的可见文本
+fn epub(raw: &[u8], _: Option<&'static Encoding>, max_size: u64) -> Result<TextSegments, String> {
+    let mut zip = ZipArchive::new(Cursor::new(raw)).map_err(|e| e.to_string())?;
+    let container = zip_entry(&mut zip, "META-INF/container.xml", max_size)?;
+    let opf_path = Document::parse(&container).map_err(|e| format!("META-INF/container.xml: {}", e))?
+        .descendants().find(|n| n.has_tag_name("rootfile")).and_then(|n| n.attribute("full-path")).map(|p| p.to_string())
+        .ok_or("META-INF/container.xml: no rootfile")?;
+    let opf = zip_entry(&mut zip, &opf_path, max_size)?;
+    let doc = Document::parse(&opf).map_err(|e| format!("{}: {}", opf_path, e))?;
+    let base = opf_path.rsplit_once('/').map(|(d, _)| format!("{}/", d)).unwrap_or_default(); // spine中的路径相对于OPF文件所在文件夹
+    let manifest = doc.descendants().filter(|n| n.has_tag_name("item")).filter_map(|n| Some((n.attribute("id")?, n.attribute("href")?))).collect::<Vec<(&str, &str)>>();
+    let (mut markup, mut texts) = (vec![opf.clone()], vec![]);
+    for idref in doc.descendants().filter(|n| n.has_tag_name("itemref")).filter_map(|n| n.attribute("idref")) {
+        let Some((_, href)) = manifest.iter().find(|(id, _)| *id == idref) else {
+            return Err(format!("{}: spine item {} not in manifest", opf_path, idref))
+        };
+        let xhtml = zip_entry(&mut zip, &format!("{}{}", base, href), max_size)?;
+        texts.push(html_text(&xhtml));
+        markup.push(xhtml);
+    }
+    Ok(TextSegments{markup: Some(markup.join("\n")), segments: texts})
+}
+
+/// PDF按页提取文本，pdf-extract遇到不支持的字体、编码等可能panic，捕获后作为错误信息返回，不中断遍历
+/// 默认的panic hook仍会打印panic信息，参考`is_extracting`
+fn pdf(raw: &[u8], _: Option<&'static Encoding>, _: u64) -> Result<TextSegments, String> {
+    EXTRACTING.with(|e| e.set(true));
+    let result = catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(raw));
+    EXTRACTING.with(|e| e.set(false));
+    match result {
+        Ok(Ok(pages)) => Ok(TextSegments{markup: None, segments: pages.iter().map(|p| tidy(p)).collect()}),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(format!("extraction failed: {}", e.downcast_ref::<String>().map(|m| m.as_str()).or(e.downcast_ref::<&str>().copied()).unwrap_or("unknown error"))),
+    }
+}
+
+/// 读取zip中指定文件的文本，头信息中的大小可能与实际不符，所以最多只解压max_size+1个byte，超过max_size返回错误
+fn zip_entry(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str, max_size: u64) -> Result<String, String> {
+    let entry = zip.by_name(name).map_err(|e| format!("{}: {}", name, e))?;
+    let mut buf = vec![];
+    entry.take(max_size.saturating_add(1)).read_to_end(&mut buf).map_err(|e| format!("{}: {}", name, e))?;
+    if buf.len() as u64 > max_size {
+        return Err(format!("{}: decompressed size > {}", name, size_limit_str(max_size)))
+    }
+    String::from_utf8(buf).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// 提取HTML中浏览器会显示的文本，跳过head、script、style等元素和带hidden属性的元素，块级元素和`<br>`换行，连续空白合并为1个空格（`<pre>`中保留）
+pub fn html_text(markup: &str) -> String {
+    let html = Html::parse_document(markup);
+    let mut text = String::new();
+    html_walk(html.root_element(), &mut text);
+    tidy(&text)
+}
+
+/// html_walk中待处理的项
+enum HtmlItem<'a> {
+    Element(ElementRef<'a>, bool), // 元素及是否在`<pre>`中
+    Text(&'a str, bool),           // 文本及是否在`<pre>`中
+    Push(char),                    // 元素结束后输出的字符
+}
+
+/// 提取HTML元素的文本，用显式的栈代替递归，嵌套很深的HTML不会栈溢出
+fn html_walk(root: ElementRef, out: &mut String) {
+    let mut stack: Vec<HtmlItem> = vec![];
+    html_children(&mut stack, root, false);
+    while let Some(item) = stack.pop() {
+        match item {
+            HtmlItem::Push(c) => out.push(c),
+            HtmlItem::Text(t, true) => out.push_str(&t.replace('\n', "\u{0}")), // pre中的换行先替换为NUL，避免被tidy合并
+            HtmlItem::Text(t, false) => {
+                let collapsed = t.split_whitespace().collect::<Vec<&str>>().join(" ");
+                if t.starts_with(char::is_whitespace) && !out.is_empty() && !out.ends_with([' ', '\t', '\n']) {
+                    out.push(' ');
+                }
+                out.push_str(&collapsed);
+                if t.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+                    out.push(' ');
+                }
+            },
+            HtmlItem::Element(e, pre) => {
+                let name = e.value().name();
+                if HIDDEN_ELEMENTS.contains(&name) || e.value().attr("hidden").is_some() {
+                    continue
+                }
+                match name {
+                    "br" => out.push('\n'),
+                    "td" | "th" => {
+                        stack.push(HtmlItem::Push('\t'));
+                        html_children(&mut stack, e, pre);
+                    },
+                    _ if BLOCK_ELEMENTS.contains(&name) => {
+                        out.push('\n');
+                        stack.push(HtmlItem::Push('\n'));
+                        html_children(&mut stack, e, pre || name == "pre");
+                    },
+                    _ => html_children(&mut stack, e, pre),
+                }
+            },
+        }
+    }
+}
+
+/// 将元素的子节点按逆序入栈，出栈时按原顺序处理
+fn html_children<'a>(stack: &mut Vec<HtmlItem<'a>>, element: ElementRef<'a>, pre: bool) {
+    for child in element.children().rev() {
+        match child.value() {
+            scraper::Node::Text(t) => stack.push(HtmlItem::Text(t, pre)),
+            scraper::Node::Element(_) => stack.push(HtmlItem::Element(ElementRef::wrap(child).unwrap(), pre)),
+            _ => {},
+        }
+    }
+}
+
+/// 提取OOXML（DOCX、PPTX）中的文本，`w:t`、`a:t`是文本，`w:p`、`a:p`是段落
+fn ooxml_text(xml: &str) -> Result<String, String> {
+    let doc = Document::parse(xml).map_err(|e| e.to_string())?;
+    let mut text = String::new();
+    ooxml_walk(doc.root(), &mut text);
+    Ok(tidy(&text))
+}
+
+/// 递归提取OOXML节点的文本
+fn ooxml_walk(node: Node, out: &mut String) {
+    for child in node.children().filter(|c| c.is_element()) {
+        match child.tag_name().name() {
+            "t" => out.push_str(child.text().unwrap_or_default()),
+            "tab" => out.push('\t'),
+            "br" | "cr" => out.push('\n'),
+            "p" => {
+                ooxml_walk(child, out);
+                out.push('\n');
+            },
+            _ => ooxml_walk(child, out),
+        }
+    }
+}
+
+/// 递归提取ODF节点的文本，`text:p`、`text:h`是段落，`text:s`是c个空格（最多MAX_ODF_SPACES个），in_par表示是否在段落中（段落外的文本是格式化的空白）
+fn odf_walk(node: Node, in_par: bool, out: &mut String) {
+    for child in node.children() {
+        if child.is_text() {
+            if in_par {
+                out.push_str(child.text().unwrap_or_default());
+            }
+            continue
+        }
+        match child.tag_name().name() {
+            "s" => out.extend(std::iter::repeat_n(' ', child.attribute(("urn:oasis:names:tc:opendocument:xmlns:text:1.0", "c")).and_then(|c| c.parse().ok()).unwrap_or(1).min(MAX_ODF_SPACES))),
+            "tab" => out.push('\t'),
+            "line-break" => out.push('\n'),
+            "p" | "h" => {
+                odf_walk(child, true, out);
+                out.push('\n');
+            },
+            _ => odf_walk(child, in_par, out),
+        }
+    }
+}
+
+/// 去除每行首尾的空白和空行
+fn tidy(text: &str) -> String {
+    text.lines().map(|l| l.trim().replace('\u{0}', "\n")).filter(|l| !l.is_empty()).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        counter::Options,
+        token::calculate_token,
+        tokenizer::test_tokenizer,
+        traverse::traverse_directory,
+    };
+
+    /// 记录调用次数的提取器，`LOGC`之后的内容作为一段文本
+    struct LogContainer(Arc<AtomicUsize>);
+
+    impl Extractor for LogContainer {
+        fn format(&self) -> &str {
+            "LOGC"
+        }
+
+        fn extract(&self, _: &Path, raw: &[u8]) -> Result<TextSegments, String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let body = raw.strip_prefix(b"LOGC").ok_or("missing LOGC header")?;
+            Ok(TextSegments{markup: None, segments: vec![String::from_utf8_lossy(body).to_string()]})
+        }
+    }
+
+    #[test]
+    fn registry_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ExtractorRegistry>();
+        assert_send_sync::<Matcher>();
+    }
+
+    #[test]
+    fn custom_extractor_is_called() {
+        let dir = std::env::temp_dir().join(format!("tokenum-extractor-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let file = dir.join("app.logc");
+        write(&file, "LOGChello world").unwrap();
+        write(dir.join("plain.txt"), "not a log container").unwrap();
+        let by_magic = Arc::new(AtomicUsize::new(0));
+        let by_predicate = Arc::new(AtomicUsize::new(0));
+        let mut options = Options::default();
+        options.extractors.register(Matcher::Magic{offset: 0, bytes: b"LOGC".to_vec()}, LogContainer(by_magic.clone()));
+        calculate_token(Some(vec![file.clone()]), None, None, "o200k_base", &options, false, None).unwrap();
+        assert_eq!(by_magic.load(Ordering::SeqCst), 1);
+        let tree = traverse_directory(&dir, test_tokenizer(), &options).unwrap();
+        assert_eq!(by_magic.load(Ordering::SeqCst), 2); // plain.txt不匹配
+        assert!(tree.contains("app.logc") && tree.contains("extracted LOGC text"));
+        // 后注册的优先
+        options.extractors.register(Matcher::Predicate(Box::new(|f, _| f.extension().is_some_and(|e| e == "logc"))), LogContainer(by_predicate.clone()));
+        traverse_directory(&dir, test_tokenizer(), &options).unwrap();
+        assert_eq!((by_magic.load(Ordering::SeqCst), by_predicate.load(Ordering::SeqCst)), (2, 1));
+        remove_dir_all(&dir).unwrap();
+    }
+
+    /// 在内存中创建zip，不压缩
+    fn zip(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+            std::io::Write::write_all(&mut writer, data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn html_visible_text() {
+        let markup = "<html><head><title>T</title><style>p{}</style></head><body>\n<h1>Title</h1><p>Hello,   <b>big</b>\n world</p><script>var x;</script>\
+                      <div hidden>secret</div><p>a<br>b</p><table><tr><td>1</td><td>2</td></tr></table><pre>  x\n  y</pre></body></html>";
+        assert_eq!(html_text(markup), "Title\nHello, big world\na\nb\n1\t2\nx\n  y"); // pre中保留缩进
+        // 按指定编码解码
+        let gbk = encoding_rs::GBK.encode("<p>中文</p>").0.into_owned();
+        assert_eq!(html(&gbk, Some(encoding_rs::GBK), u64::MAX).unwrap().segments, ["中文"]);
+        // 嵌套很深的HTML不会栈溢出
+        let deep = format!("{}deep{}", "<span>".repeat(100000), "</span>".repeat(100000));
+        assert_eq!(html_text(&deep), "deep");
+    }
+
+    #[test]
+    fn docx_text() {
+        let xml = r#"<w:document xmlns:w="w"><w:body><w:p><w:r><w:t>Hello</w:t><w:tab/><w:t>world</w:t></w:r></w:p><w:p><w:r><w:t>Second</w:t><w:br/><w:t>line</w:t></w:r></w:p></w:body></w:document>"#;
+        let out = docx(&zip(&[("word/document.xml", xml)]), None, u64::MAX).unwrap();
+        assert_eq!(out.segments, ["Hello\tworld\nSecond\nline"]);
+        assert_eq!(out.markup.as_deref(), Some(xml));
+        assert!(docx(&zip(&[("other.xml", xml)]), None, u64::MAX).err().unwrap().starts_with("word/document.xml: "));
+        assert!(docx(b"not a zip", None, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn odf_text() {
+        let xml = r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+            <office:body><office:text><text:h>Heading</text:h><text:p>a<text:s text:c="3"/>b<text:tab/>c<text:line-break/>d</text:p><text:p>e<text:s text:c="4000000000"/>f</text:p></office:text></office:body></office:document-content>"#;
+        let out = odf(&zip(&[("content.xml", xml)]), None, u64::MAX).unwrap();
+        assert_eq!(out.segments[0].lines().take(3).collect::<Vec<&str>>(), ["Heading", "a   b\tc", "d"]);
+        assert_eq!(out.segments[0].lines().nth(3).unwrap(), format!("e{}f", " ".repeat(MAX_ODF_SPACES))); // 展开的空格数有上限
+        assert_eq!(odf(&zip(&[("content.xml", "<a/>")]), None, u64::MAX).err().unwrap(), "content.xml: no office:body");
+    }
+
+    #[test]
+    fn epub_chapters() {
+        let container = r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#;
+        let opf = r#"<package><manifest><item id="c1" href="one.xhtml"/><item id="c2" href="two.xhtml"/></manifest><spine><itemref idref="c2"/><itemref idref="c1"/></spine></package>"#;
+        let raw = zip(&[("META-INF/container.xml", container), ("OEBPS/content.opf", opf), ("OEBPS/one.xhtml", "<html><body><p>One</p></body></html>"), ("OEBPS/two.xhtml", "<html><body><p>Two</p></body></html>")]);
+        assert_eq!(epub(&raw, None, u64::MAX).unwrap().segments, ["Two", "One"]); // 按spine的顺序
+        let missing = r#"<package><manifest/><spine><itemref idref="x"/></spine></package>"#;
+        let raw = zip(&[("META-INF/container.xml", container), ("OEBPS/content.opf", missing)]);
+        assert_eq!(epub(&raw, None, u64::MAX).err().unwrap(), "OEBPS/content.opf: spine item x not in manifest");
+    }
+
+    #[test]
+    fn oversize_entry() {
+        let xml = format!(r#"<w:document xmlns:w="w"><w:body><w:p><w:r><w:t>{}</w:t></w:r></w:p></w:body></w:document>"#, "a".repeat(4096));
+        let raw = zip(&[("word/document.xml", &xml)]);
+        assert_eq!(docx(&raw, None, 1024).err().unwrap(), "word/document.xml: decompressed size > 1Kb");
+        assert!(docx(&raw, None, 8192).is_ok());
+        // 按Options中的-m限制，作为提取错误返回
+        let registry = ExtractorRegistry::builtin();
+        let options = Options::default().max_size(1024);
+        let out = registry.extract(test_tokenizer(), Path::new("a.docx"), &raw, &options).unwrap();
+        assert_eq!(out.err().unwrap(), "invalid DOCX: word/document.xml: decompressed size > 1Kb");
+        assert!(registry.extract(test_tokenizer(), Path::new("a.txt"), &raw, &options).is_none());
+    }
+}
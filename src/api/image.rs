@@ -0,0 +1,76 @@
+use imagesize::{blob_size, image_type, ImageType};
+
+/// 图片token计算方式，对应vision输入的detail参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageDetail {
+    Low,  // 固定85个token
+    High, // 按512x512的tile计算
+}
+
+/// 从文件头读取PNG、JPEG、WebP、GIF图片的宽和高，不是这4种图片则返回None
+pub fn image_size(raw: &[u8]) -> Option<(usize, usize)> {
+    match image_type(raw) {
+        Ok(ImageType::Png | ImageType::Jpeg | ImageType::Webp | ImageType::Gif) => match blob_size(raw) {
+            Ok(s) if s.width > 0 && s.height > 0 => Some((s.width, s.height)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// 估算图片作为vision输入的token数
+/// low：固定85个token
+/// high：先等比缩放至2048x2048范围内，再等比缩放使短边不超过768，然后计算需要多少个512x512的tile，每个tile 170个token，再加上固定的85个token
+/// 参考：https://platform.openai.com/docs/guides/vision#calculating-costs
+pub fn image_token(width: usize, height: usize, detail: ImageDetail) -> usize {
+    match detail {
+        ImageDetail::Low => 85,
+        ImageDetail::High => {
+            let (mut w, mut h) = (width as f64, height as f64);
+            // 缩放至2048x2048范围内
+            if w > 2048.0 || h > 2048.0 {
+                let scale = 2048.0 / w.max(h);
+                w *= scale;
+                h *= scale;
+            }
+            // 缩放使短边为768
+            if w.min(h) > 768.0 {
+                let scale = 768.0 / w.min(h);
+                w *= scale;
+                h *= scale;
+            }
+            let tiles = (w / 512.0).ceil() as usize * (h / 512.0).ceil() as usize;
+            170 * tiles + 85
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_detail_is_fixed() {
+        assert_eq!(image_token(4096, 8192, ImageDetail::Low), 85);
+        assert_eq!(image_token(1, 1, ImageDetail::Low), 85);
+    }
+
+    #[test]
+    fn high_detail_tiles() {
+        // OpenAI文档中的例子
+        assert_eq!(image_token(1024, 1024, ImageDetail::High), 765); // 缩放至768x768，4个tile
+        assert_eq!(image_token(2048, 4096, ImageDetail::High), 1105); // 缩放至1024x2048再至768x1536，6个tile
+        assert_eq!(image_token(512, 512, ImageDetail::High), 255); // 不缩放，1个tile
+        assert_eq!(image_token(100, 3000, ImageDetail::High), 85 + 170 * 4); // 缩放至68x2048，短边小于768不再缩放
+    }
+
+    #[test]
+    fn png_size() {
+        let mut raw = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        raw.extend_from_slice(&640u32.to_be_bytes());
+        raw.extend_from_slice(&480u32.to_be_bytes());
+        raw.extend_from_slice(&[8, 2, 0, 0, 0]);
+        assert_eq!(image_size(&raw), Some((640, 480)));
+        assert_eq!(image_size(b"not an image"), None);
+    }
+}
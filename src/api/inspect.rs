@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedInspect, ParsedParas},
+    token::{
+        SpecialTokens,
+        encode,
+        get_tokenizer,
+        read_text,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 交替使用的背景色，用于在终端中标记token边界
+const COLORS: [&str; 2] = ["\x1b[30;46m", "\x1b[30;43m"];
+const RESET: &str = "\x1b[0m";
+
+/// 执行inspect子命令，打印-s字符串和-f文件每个token的ID、byte数和解码后的文本，--color则用交替的背景色显示token边界
+pub fn inspect_input(paras: &ParsedParas, inspect: &ParsedInspect) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取的文件及错误状态
+    if let Some(files) = &paras.files {
+        for f in files {
+            let text = match read_text(f, &paras.options) {
+                Ok(t) => t,
+                Err(e) => { // 无法读取的文件，--fail-fast时返回错误
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            println!("{}", inspect_text(&bpe, &f.display().to_string(), &text, inspect.color, paras.options.special));
+        }
+    }
+    for (path, status) in &skipped {
+        eprintln!("[skip]: {} {}", path.display(), status);
+    }
+    if let Some(s) = &paras.string {
+        println!("{}", inspect_text(&bpe, "-s string", s, inspect.color, paras.options.special));
+    }
+    Ok(())
+}
+
+/// 返回字符串每个token的信息，color为true时返回用交替背景色标记token边界的原始文本
+/// 一个多字节字符可能被拆分到多个token中，单独解码这些token时显示为16进制byte，标记颜色时合并为一组
+pub fn inspect_text(bpe: &dyn Tokenizer, name: &str, text: &str, color: bool, special: SpecialTokens) -> String {
+    let tokens = encode(bpe, text, special);
+    let mut out: Vec<String> = vec![format!("{}: {} tokens", name, tokens.len())];
+    let token_bytes: Vec<Vec<u8>> = tokens.iter().map(|t| bpe.token_bytes(*t).unwrap_or_default()).collect();
+    if color {
+        let mut colored = String::new();
+        let mut pending: Vec<u8> = vec![]; // 还不能组成完整字符的byte
+        let mut i = 0; // 已输出的组数，用于交替颜色
+        for b in token_bytes {
+            pending.extend(b);
+            if let Ok(s) = std::str::from_utf8(&pending) {
+                colored += &format!("{}{}{}", COLORS[i % 2], s, RESET);
+                pending.clear();
+                i += 1;
+            }
+        }
+        if !pending.is_empty() {
+            colored += &format!("{}{}{}", COLORS[i % 2], String::from_utf8_lossy(&pending), RESET);
+        }
+        out.push(colored);
+    } else {
+        let width = tokens.len().saturating_sub(1).to_string().len(); // 序号的宽度
+        for (i, (id, b)) in tokens.iter().zip(token_bytes).enumerate() {
+            let decoded = match std::str::from_utf8(&b) {
+                Ok(s) => format!("{:?}", s),
+                Err(_) => format!("<{}>", b.iter().map(|x| format!("{:02x}", x)).collect::<Vec<String>>().join(" ")), // 不完整的UTF-8字符
+            };
+            out.push(format!("{:>width$}  id {:<7} {:>3} bytes  {}", i, id, b.len(), decoded, width = width));
+        }
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    #[test]
+    fn token_ids() {
+        let out = inspect_text(test_tokenizer(), "-s string", "hello world", false, SpecialTokens::Allow);
+        assert_eq!(out, "-s string: 2 tokens\n0  id 24912     5 bytes  \"hello\"\n1  id 2375      6 bytes  \" world\"");
+        // 特殊token按处理方式编码
+        assert_eq!(inspect_text(test_tokenizer(), "x", "<|endoftext|>", false, SpecialTokens::Allow), "x: 1 tokens\n0  id 199999   13 bytes  \"<|endoftext|>\"");
+        assert!(inspect_text(test_tokenizer(), "x", "<|endoftext|>", false, SpecialTokens::Text).starts_with("x: 7 tokens\n"));
+        // 序号按最大序号对齐
+        let text = (0..12).map(|i| format!("{}", i)).collect::<Vec<String>>().join(",");
+        let n = encode(test_tokenizer(), &text, SpecialTokens::Allow).len();
+        assert!(n > 10);
+        let out = inspect_text(test_tokenizer(), "x", &text, false, SpecialTokens::Allow);
+        assert!(out.lines().nth(1).unwrap().starts_with(" 0  id "));
+        assert!(out.lines().last().unwrap().starts_with(&format!("{}  id ", n - 1)));
+    }
+
+    #[test]
+    fn multibyte() {
+        // 2 byte的字符是单个token
+        assert_eq!(inspect_text(test_tokenizer(), "x", "é", false, SpecialTokens::Allow), "x: 1 tokens\n0  id 377       2 bytes  \"é\"");
+        // 4 byte的字符拆分为多个token，单独解码显示为16进制byte
+        let out = inspect_text(test_tokenizer(), "x", "🦀", false, SpecialTokens::Allow);
+        assert_eq!(out, "x: 3 tokens\n0  id 4103      2 bytes  <f0 9f>\n1  id 99        1 bytes  <a6>\n2  id 222       1 bytes  <80>");
+    }
+
+    #[test]
+    fn color() {
+        let out = inspect_text(test_tokenizer(), "x", "hello world", true, SpecialTokens::Allow);
+        assert_eq!(out, format!("x: 2 tokens\n{}hello{}{} world{}", COLORS[0], RESET, COLORS[1], RESET));
+        // 拆分的多字节字符合并为一组，之后的token继续交替颜色
+        let out = inspect_text(test_tokenizer(), "x", "🦀 hello", true, SpecialTokens::Allow);
+        assert_eq!(out, format!("x: 4 tokens\n{}🦀{}{} hello{}", COLORS[0], RESET, COLORS[1], RESET));
+        assert_eq!(inspect_text(test_tokenizer(), "x", "", true, SpecialTokens::Allow), "x: 0 tokens\n");
+    }
+}
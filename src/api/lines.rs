@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedLines, ParsedParas},
+    token::{
+        SpecialTokens,
+        get_tokenizer,
+        read_text,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 高亮token数最多的块
+const HIGHLIGHT: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// 执行lines子命令，打印-f每个文件每行（或每-l行）的token数及条形图，并列出token数最多的--top-lines个块
+pub fn lines_files(paras: &ParsedParas, lines: &ParsedLines) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取的文件及错误状态
+    if let Some(files) = &paras.files {
+        for f in files {
+            let text = match read_text(f, &paras.options) {
+                Ok(t) => t,
+                Err(e) => { // 无法读取的文件，--fail-fast时返回错误
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            println!("{}", line_heatmap(&bpe, &f.display().to_string(), &text, lines, paras.options.special));
+        }
+    }
+    for (path, status) in &skipped {
+        eprintln!("[skip]: {} {}", path.display(), status);
+    }
+    Ok(())
+}
+
+/// 计算每行的token数，每个token计入其起始byte所在的行，所有行之和等于整个文件的token数
+pub fn line_tokens(bpe: &dyn Tokenizer, text: &str, special: SpecialTokens) -> Vec<usize> {
+    let line_starts: Vec<usize> = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1).filter(|&i| i < text.len())).collect(); // 每行的起始byte位置
+    let mut tokens = vec![0; line_starts.len()];
+    let offsets = token_offsets(bpe, text, special);
+    let mut line = 0;
+    for &start in &offsets[..offsets.len() - 1] {
+        while line + 1 < line_starts.len() && line_starts[line + 1] <= start {
+            line += 1;
+        }
+        tokens[line] += 1;
+    }
+    tokens
+}
+
+/// 返回每个块的行号范围、token数及条形图，token数最多的top个块用`*`标记（--color时高亮），最后列出这些块
+fn line_heatmap(bpe: &dyn Tokenizer, name: &str, text: &str, lines: &ParsedLines, special: SpecialTokens) -> String {
+    let per_line = line_tokens(bpe, text, special);
+    let total: usize = per_line.iter().sum();
+    // 每block行合并为一个块，(起始行号, 结束行号, token数)
+    let blocks: Vec<(usize, usize, usize)> = per_line.chunks(lines.block).enumerate().map(|(i, c)| (i * lines.block + 1, i * lines.block + c.len(), c.iter().sum())).collect();
+    let mut top: Vec<usize> = (0..blocks.len()).filter(|&i| blocks[i].2 > 0).collect();
+    top.sort_by_key(|&i| (std::cmp::Reverse(blocks[i].2), i));
+    top.truncate(lines.top);
+    let max = blocks.iter().map(|b| b.2).max().unwrap_or(0);
+    let range_width = format!("{}-{}", per_line.len(), per_line.len()).len();
+    let token_width = max.to_string().len();
+    let mut out: Vec<String> = vec![format!("{} ({} tokens, {} lines)", name, total, per_line.len())];
+    for (i, (from, to, tokens)) in blocks.iter().enumerate() {
+        let range = line_range(*from, *to);
+        let bar = if max == 0 { String::new() } else { "█".repeat((tokens * lines.width).div_ceil(max)) };
+        let line = format!("{:>range_width$} {:>token_width$} {}", range, tokens, bar).trim_end().to_string();
+        if top.contains(&i) {
+            if lines.color {
+                out.push(format!("{}{} *{}", HIGHLIGHT, line, RESET));
+            } else {
+                out.push(format!("{} *", line));
+            }
+        } else {
+            out.push(line);
+        }
+    }
+    if !top.is_empty() {
+        out.push(format!("top {} spans:", top.len()));
+        for i in top {
+            let (from, to, tokens) = blocks[i];
+            let label = if from == to { "line" } else { "lines" }; // 单行显示`line 11`
+            out.push(format!("  {} {}: {} tokens ({:.1}%)", label, line_range(from, to), tokens, tokens as f64 * 100.0 / total as f64));
+        }
+    }
+    out.join("\n")
+}
+
+/// 行号范围，例如`3`、`11-20`
+fn line_range(from: usize, to: usize) -> String {
+    if from == to {
+        from.to_string()
+    } else {
+        format!("{}-{}", from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::count;
+    use crate::tokenizer::test_tokenizer;
+
+    fn parsed(block: usize, top: usize) -> ParsedLines {
+        ParsedLines{block, top, width: 10, color: false}
+    }
+
+    #[test]
+    fn per_line() {
+        let text = "hello world\nfoo\n\nbar baz qux\n";
+        let tokens = line_tokens(test_tokenizer(), text, SpecialTokens::Allow);
+        assert_eq!(tokens.len(), 4); // 结尾的换行不算新的一行
+        assert_eq!(tokens.iter().sum::<usize>(), count(test_tokenizer(), text, SpecialTokens::Allow));
+        assert_eq!(tokens[0], count(test_tokenizer(), "hello world\n", SpecialTokens::Allow)); // 行尾的换行计入该行
+        assert!(tokens[3] >= 3);
+        assert_eq!(line_tokens(test_tokenizer(), "", SpecialTokens::Allow), vec![0]);
+        // 跨行的token（例如连续的换行）计入起始byte所在的行
+        let text = "a\n\n\n\nb";
+        let tokens = line_tokens(test_tokenizer(), text, SpecialTokens::Allow);
+        assert_eq!(tokens.iter().sum::<usize>(), count(test_tokenizer(), text, SpecialTokens::Allow));
+        assert_eq!(tokens[1..4], [0, 0, 0]);
+        // 多字节字符拆分到多个token中
+        let tokens = line_tokens(test_tokenizer(), "🦀\n🦀🦀", SpecialTokens::Allow);
+        assert_eq!(tokens.iter().sum::<usize>(), count(test_tokenizer(), "🦀\n🦀🦀", SpecialTokens::Allow));
+        assert!(tokens[1] >= 4);
+    }
+
+    #[test]
+    fn top_spans() {
+        let text = "a\nword word word word word word\nb\nword word word\n";
+        let per_line = line_tokens(test_tokenizer(), text, SpecialTokens::Allow);
+        let total: usize = per_line.iter().sum();
+        let out = line_heatmap(test_tokenizer(), "x.txt", text, &parsed(1, 2), SpecialTokens::Allow);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("x.txt ({} tokens, 4 lines)", total));
+        assert!(lines[2].ends_with(" *") && lines[4].ends_with(" *"));
+        assert!(!lines[1].ends_with(" *") && !lines[3].ends_with(" *"));
+        assert_eq!(lines[2].matches('█').count(), 10); // 最多的块为完整宽度
+        assert_eq!(lines[5], "top 2 spans:");
+        assert_eq!(lines[6], format!("  line 2: {} tokens ({:.1}%)", per_line[1], per_line[1] as f64 * 100.0 / total as f64));
+        assert!(lines[7].starts_with("  line 4: "));
+        // 每2行合并为一个块
+        let out = line_heatmap(test_tokenizer(), "x.txt", text, &parsed(2, 1), SpecialTokens::Allow);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[1].starts_with("1-2 ") && lines[1].ends_with(" *"));
+        assert!(lines[2].starts_with("3-4 "));
+        assert_eq!(lines[4], format!("  lines 1-2: {} tokens ({:.1}%)", per_line[0] + per_line[1], (per_line[0] + per_line[1]) as f64 * 100.0 / total as f64));
+        // 空文件没有top块
+        assert_eq!(line_heatmap(test_tokenizer(), "e.txt", "", &parsed(1, 5), SpecialTokens::Allow), "e.txt (0 tokens, 1 lines)\n  1 0");
+    }
+}
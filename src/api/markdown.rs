@@ -0,0 +1,163 @@
+use std::ops::Range;
+use std::path::PathBuf;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use termtree::Tree;
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedMarkdown, ParsedParas},
+    token::{
+        SpecialTokens,
+        get_tokenizer,
+        read_text,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// Markdown中的一个章节，由标题开始，到下一个同级或更高级标题结束
+struct Section {
+    level:    usize,      // 标题级别，文件本身为0
+    title:    String,     // 标题文本
+    start:    usize,      // 起始byte位置
+    prose:    usize,      // 正文（包括标题、列表、引用等）token数，包含子章节
+    code:     usize,      // 代码块token数，包含子章节
+    table:    usize,      // 表格token数，包含子章节
+    children: Vec<usize>, // 子章节的索引
+}
+
+/// 执行markdown子命令，以tree的形式打印-f每个Markdown文件每个章节的token数，以及正文、代码块、表格各自的token数
+pub fn markdown_files(paras: &ParsedParas, markdown: &ParsedMarkdown) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取的文件及错误状态
+    if let Some(files) = &paras.files {
+        for f in files {
+            let text = match read_text(f, &paras.options) {
+                Ok(t) => t,
+                Err(e) => { // 无法读取的文件，--fail-fast时返回错误
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            println!("{}", markdown_tree(&bpe, &f.display().to_string(), &text, markdown.depth, paras.options.special));
+        }
+    }
+    for (path, status) in &skipped {
+        eprintln!("[skip]: {} {}", path.display(), status);
+    }
+    Ok(())
+}
+
+/// 解析Markdown标题、代码块、表格，将每个token按起始byte位置计入所在章节及类型，返回tree字符串
+/// 只显示depth级及以上的标题，更深的章节合并到父章节中
+pub fn markdown_tree(bpe: &dyn Tokenizer, name: &str, text: &str, depth: usize, special: SpecialTokens) -> String {
+    let mut sections: Vec<Section> = vec![Section{level: 0, title: name.to_string(), start: 0, prose: 0, code: 0, table: 0, children: vec![]}];
+    let mut code_ranges: Vec<Range<usize>> = vec![];
+    let mut table_ranges: Vec<Range<usize>> = vec![];
+    let mut in_heading = false;
+    for (event, range) in Parser::new_ext(text, Options::ENABLE_TABLES).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading{level, ..}) if level as usize <= depth => {
+                sections.push(Section{level: level as usize, title: String::new(), start: range.start, prose: 0, code: 0, table: 0, children: vec![]});
+                in_heading = true;
+            },
+            Event::End(TagEnd::Heading(_)) => in_heading = false,
+            Event::Text(t) | Event::Code(t) if in_heading => sections.last_mut().unwrap().title += &t,
+            Event::Start(Tag::CodeBlock(_)) => code_ranges.push(range),
+            Event::Start(Tag::Table(_)) => table_ranges.push(range),
+            _ => (),
+        }
+    }
+    // 每个token按起始byte位置计入所在章节及类型
+    let offsets = token_offsets(bpe, text, special);
+    for &pos in &offsets[..offsets.len() - 1] {
+        let i = sections.partition_point(|s| s.start <= pos).saturating_sub(1);
+        if code_ranges.iter().any(|r| r.contains(&pos)) {
+            sections[i].code += 1;
+        } else if table_ranges.iter().any(|r| r.contains(&pos)) {
+            sections[i].table += 1;
+        } else {
+            sections[i].prose += 1;
+        }
+    }
+    // 确定父子关系：父章节是前面最近的级别更小的章节
+    let mut parents: Vec<usize> = vec![0; sections.len()];
+    for i in 1..sections.len() {
+        parents[i] = (0..i).rev().find(|&j| sections[j].level < sections[i].level).unwrap_or(0);
+        sections[parents[i]].children.push(i);
+    }
+    // 子章节在父章节之后，从后往前把子章节的token数加到父章节上
+    for i in (1..sections.len()).rev() {
+        let (prose, code, table) = (sections[i].prose, sections[i].code, sections[i].table);
+        sections[parents[i]].prose += prose;
+        sections[parents[i]].code += code;
+        sections[parents[i]].table += table;
+    }
+    build_tree(&sections, 0).to_string().trim_end().to_string()
+}
+
+/// 递归创建章节tree
+fn build_tree(sections: &[Section], i: usize) -> Tree<String> {
+    let s = &sections[i];
+    let total = s.prose + s.code + s.table;
+    let title = if s.level == 0 {
+        s.title.clone()
+    } else {
+        format!("{} {}", "#".repeat(s.level), s.title)
+    };
+    let mut parts: Vec<String> = vec![];
+    for (kind, num) in [("prose", s.prose), ("code", s.code), ("table", s.table)] {
+        if num > 0 {
+            parts.push(format!("{} {}", kind, num));
+        }
+    }
+    let label = if s.code + s.table > 0 {
+        format!("{} (total {} tokens: {})", title, total, parts.join(", "))
+    } else {
+        format!("{} ({} tokens)", title, total)
+    };
+    Tree::new(label).with_leaves(s.children.iter().map(|&c| build_tree(sections, c)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::count;
+    use crate::tokenizer::test_tokenizer;
+
+    const DOC: &str = "intro text here\n\n# One\n\nfirst section\n\n## Sub\n\n```rust\nfn main() {}\n```\n\n# Two\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+
+    #[test]
+    fn heading_tree() {
+        let out = markdown_tree(test_tokenizer(), "doc.md", DOC, 6, SpecialTokens::Allow);
+        assert_eq!(out, "doc.md (total 42 tokens: prose 16, code 9, table 17)\n\
+                         ├── # One (total 18 tokens: prose 9, code 9)\n\
+                         │   └── ## Sub (total 12 tokens: prose 3, code 9)\n\
+                         └── # Two (total 20 tokens: prose 3, table 17)");
+        assert_eq!(count(test_tokenizer(), DOC, SpecialTokens::Allow), 42);
+        // 第一个标题之前的文本计入文件本身：42 = intro 4 + One 18 + Two 20
+        assert_eq!(count(test_tokenizer(), "intro text here\n\n", SpecialTokens::Allow), 4);
+    }
+
+    #[test]
+    fn depth() {
+        // 更深的章节合并到父章节中，总数不变
+        let out = markdown_tree(test_tokenizer(), "doc.md", DOC, 1, SpecialTokens::Allow);
+        assert_eq!(out, "doc.md (total 42 tokens: prose 16, code 9, table 17)\n\
+                         ├── # One (total 18 tokens: prose 9, code 9)\n\
+                         └── # Two (total 20 tokens: prose 3, table 17)");
+        let text = "just prose, no headings\n";
+        assert_eq!(markdown_tree(test_tokenizer(), "a.md", text, 6, SpecialTokens::Allow), format!("a.md ({} tokens)", count(test_tokenizer(), text, SpecialTokens::Allow)));
+        assert_eq!(markdown_tree(test_tokenizer(), "e.md", "", 6, SpecialTokens::Allow), "e.md (0 tokens)");
+    }
+
+    #[test]
+    fn heading_title() {
+        // 标题中的行内代码作为标题文本；文件以标题开头时，所有token都在该章节中
+        let out = markdown_tree(test_tokenizer(), "a.md", "# Use `cargo`\n\ntext\n", 6, SpecialTokens::Allow);
+        let total = count(test_tokenizer(), "# Use `cargo`\n\ntext\n", SpecialTokens::Allow);
+        assert_eq!(out, format!("a.md ({} tokens)\n└── # Use cargo ({} tokens)", total, total));
+    }
+}
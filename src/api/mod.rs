@@ -2,3 +2,22 @@ pub mod parse_paras;
 pub mod token;
 pub mod error;
 pub mod traverse;
+pub mod tools;
+pub mod image;
+pub mod chunk;
+pub mod truncate;
+pub mod pack;
+pub mod inspect;
+pub mod decode;
+pub mod lines;
+pub mod markdown;
+pub mod structure;
+pub mod symbols;
+pub mod breakdown;
+pub mod normalize;
+pub mod binary;
+pub mod notebook;
+pub mod archive;
+pub mod extract;
+pub mod tokenizer;
+pub mod counter;
@@ -0,0 +1,130 @@
+/// 计算token前对文本的规范化处理，按BOM、CRLF、行尾空白、tab、空行的顺序执行
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Normalize {
+    pub bom:      bool,  // 去除开头的BOM
+    pub crlf:     bool,  // CRLF转为LF
+    pub trailing: bool,  // 去除每行行尾空白
+    pub tabs:     usize, // tab展开为空格时的tab宽度，0表示不展开
+    pub blank:    bool,  // 多个连续空行合并为1个
+}
+
+impl Normalize {
+    /// 解析`--normalize`的逗号间隔列表，支持crlf、trailing、tabs、blank、bom、all
+    pub fn parse(items: &str, tab_width: usize) -> Result<Self, String> {
+        let mut out = Normalize::default();
+        for item in items.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+            match item {
+                "bom" => out.bom = true,
+                "crlf" => out.crlf = true,
+                "trailing" => out.trailing = true,
+                "tabs" => out.tabs = tab_width,
+                "blank" => out.blank = true,
+                "all" => out = Normalize{bom: true, crlf: true, trailing: true, tabs: tab_width, blank: true},
+                i => return Err(i.to_string()),
+            }
+        }
+        Ok(out)
+    }
+
+    /// 是否没有任何规范化处理
+    pub fn is_empty(&self) -> bool {
+        !(self.bom || self.crlf || self.trailing || self.tabs > 0 || self.blank)
+    }
+
+    /// 返回规范化后的文本
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text;
+        if self.bom {
+            text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        }
+        let mut out = if self.crlf {
+            text.replace("\r\n", "\n")
+        } else {
+            text.to_string()
+        };
+        if self.trailing || self.tabs > 0 || self.blank {
+            let mut lines: Vec<String> = vec![];
+            let mut prev_blank = false;
+            for line in out.split_inclusive('\n') {
+                let content = line.trim_end_matches(['\r', '\n']);
+                let ending = &line[content.len()..]; // 保留原来的换行符
+                let mut content = if self.trailing { content.trim_end().to_string() } else { content.to_string() };
+                if self.tabs > 0 {
+                    content = expand_tabs(&content, self.tabs);
+                }
+                let blank = content.trim().is_empty();
+                if self.blank && blank && prev_blank {
+                    continue
+                }
+                prev_blank = blank;
+                lines.push(content + ending);
+            }
+            out = lines.concat();
+        }
+        out
+    }
+}
+
+/// 将tab展开为空格，对齐到下一个tab宽度的整数倍列
+fn expand_tabs(line: &str, width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let n = width - col % width;
+            out.extend(std::iter::repeat_n(' ', n));
+            col += n;
+        } else {
+            out.push(c);
+            col += 1;
+        }
+    }
+    out
+}
+
+/// 文件或字符串token数的描述，指定了规范化时同时显示原始和规范化后的token数
+pub fn tokens_str(raw: Option<usize>, tokens: usize) -> String {
+    match raw {
+        Some(r) => format!("raw {} tokens, normalized {} tokens", r, tokens),
+        None => format!("{} tokens", tokens),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(items: &str, text: &str) -> String {
+        Normalize::parse(items, 4).unwrap().apply(text)
+    }
+
+    #[test]
+    fn parse_items() {
+        assert!(Normalize::parse("", 4).unwrap().is_empty());
+        let n = Normalize::parse(" crlf , tabs", 2).unwrap();
+        assert!(n.crlf && !n.bom && n.tabs == 2);
+        let n = Normalize::parse("all", 8).unwrap();
+        assert!(n.bom && n.crlf && n.trailing && n.blank && n.tabs == 8);
+        assert_eq!(Normalize::parse("crlf,spaces", 4).unwrap_err(), "spaces");
+    }
+
+    #[test]
+    fn each_item() {
+        assert_eq!(apply("bom", "\u{feff}a\u{feff}"), "a\u{feff}");
+        assert_eq!(apply("crlf", "a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(apply("trailing", "a \t\nb  \r\n c"), "a\nb\r\n c");
+        assert_eq!(apply("tabs", "\ta\tbc\td\n"), "    a   bc  d\n");
+        assert_eq!(apply("blank", "a\n\n\n  \nb\n\n"), "a\n\nb\n\n");
+    }
+
+    #[test]
+    fn unchanged_without_items() {
+        let text = "\u{feff}a \r\n\n\n\tb";
+        assert_eq!(apply("", text), text);
+    }
+
+    #[test]
+    fn all_items() {
+        assert_eq!(apply("all", "\u{feff}a  \r\n\r\n \t\r\n\tb\r\n"), "a\n\n    b\n");
+    }
+}
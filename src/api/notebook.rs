@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use serde_json::Value;
+use termtree::Tree;
+
+use crate::{
+    token::{
+        SpecialTokens,
+        count,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 按prompt中的形式提取的Jupyter notebook
+pub struct Notebook {
+    pub tokens: usize,             // 所有cell合并后的token数
+    pub raw:    usize,             // 原始JSON的token数
+    pub cells:  Vec<Tree<String>>, // 每个cell的token数，作为文件节点的子节点
+}
+
+impl Notebook {
+    /// 显示在文件大小后面的描述
+    pub fn label(&self) -> String {
+        let saves = if self.raw > self.tokens {
+            format!("saves {} tokens ({:.1}%)", self.raw - self.tokens, (self.raw - self.tokens) as f64 * 100.0 / self.raw as f64)
+        } else {
+            "saves 0 token".to_string()
+        };
+        format!("notebook {} cells, {} tokens, raw JSON {} tokens, {}", self.cells.len(), self.tokens, self.raw, saves)
+    }
+}
+
+/// 提取`.ipynb`文件的cell，不是`.ipynb`文件返回None，不是有效的notebook返回原因，由调用者按原始JSON计算并显示原因
+/// markdown和raw cell保留原文，code cell放在代码块中，outputs为true时加上stream、execute_result、display_data的文本输出和error信息，cell之间空一行
+pub fn notebook(bpe: &dyn Tokenizer, file: &Path, text: &str, outputs: bool, special: SpecialTokens) -> Option<Result<Notebook, String>> {
+    if !file.extension().is_some_and(|e| e.eq_ignore_ascii_case("ipynb")) {
+        return None
+    }
+    let json: Value = match serde_json::from_str(text) {
+        Ok(j) => j,
+        Err(e) => return Some(Err(format!("invalid JSON: {}", e))),
+    };
+    let Some(cells) = json["cells"].as_array() else {
+        return Some(Err("no cells".to_string()))
+    };
+    let lang = json["metadata"]["kernelspec"]["language"].as_str().or(json["metadata"]["language_info"]["name"].as_str()).unwrap_or("python");
+    let mut rendered: Vec<String> = vec![];
+    let mut leaves: Vec<Tree<String>> = vec![];
+    for (i, cell) in cells.iter().enumerate() {
+        let kind = cell["cell_type"].as_str().unwrap_or("raw");
+        let source = join_text(&cell["source"]);
+        let mut out = match kind {
+            "code" => format!("```{}\n{}\n```", lang, source.trim_end_matches('\n')),
+            _ => source,
+        };
+        let source_tokens = count(bpe, &out, special);
+        let mut label = format!("[{}] {} ({} tokens)", i + 1, kind, source_tokens);
+        if outputs && kind == "code" {
+            let texts: Vec<String> = cell["outputs"].as_array().into_iter().flatten().filter_map(output_text).collect();
+            if !texts.is_empty() {
+                let output = format!("Output:\n```\n{}\n```", texts.join("\n").trim_end_matches('\n'));
+                let output_tokens = count(bpe, &output, special);
+                out = format!("{}\n{}", out, output);
+                label = format!("[{}] {} ({} tokens: source {}, outputs {})", i + 1, kind, count(bpe, &out, special), source_tokens, output_tokens);
+            }
+        }
+        rendered.push(out);
+        leaves.push(Tree::new(label));
+    }
+    Some(Ok(Notebook{
+        tokens: count(bpe, &rendered.join("\n\n"), special),
+        raw: count(bpe, text, special),
+        cells: leaves,
+    }))
+}
+
+/// 单个output的文本，图片、HTML等非文本输出返回None
+fn output_text(output: &Value) -> Option<String> {
+    match output["output_type"].as_str()? {
+        "stream" => Some(join_text(&output["text"])),
+        "execute_result" | "display_data" => output["data"].get("text/plain").map(join_text),
+        "error" => Some(format!("{}: {}", output["ename"].as_str().unwrap_or_default(), output["evalue"].as_str().unwrap_or_default())),
+        _ => None,
+    }
+}
+
+/// notebook中的文本可以是字符串，也可以是按行拆分的字符串数组
+fn join_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a.iter().filter_map(|l| l.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    #[test]
+    fn fallback_reason() {
+        let nb = |text: &str| notebook(test_tokenizer(), Path::new("a.ipynb"), text, false, SpecialTokens::Allow);
+        assert!(notebook(test_tokenizer(), Path::new("a.json"), "{}", false, SpecialTokens::Allow).is_none());
+        assert_eq!(nb("{\"a\": 1}").unwrap().err().unwrap(), "no cells");
+        assert!(nb("{bad").unwrap().err().unwrap().starts_with("invalid JSON: "));
+        let ok = nb(r##"{"cells": [{"cell_type": "markdown", "source": ["# Title\n"]}, {"cell_type": "code", "source": "print(1)"}]}"##).unwrap().ok().unwrap();
+        assert_eq!(ok.cells.len(), 2);
+        assert_eq!(ok.tokens, count(test_tokenizer(), "# Title\n\n\n```python\nprint(1)\n```", SpecialTokens::Allow));
+    }
+}
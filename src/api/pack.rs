@@ -0,0 +1,238 @@
+use std::fs::write;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobMatcher};
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedPack, ParsedParas},
+    token::{
+        FileToken,
+        SpecialTokens,
+        count,
+        file_len,
+        get_tokenizer,
+        size_limit_str,
+    },
+    tokenizer::Tokenizer,
+    traverse::text_files,
+};
+
+/// 每个文件在打包文档中的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackFormat {
+    Markdown, // `## 路径`加代码块
+    Xml,      // `<file path="路径">...</file>`
+}
+
+/// 超出预算时优先保留哪些文件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackOrder {
+    Path,     // 按遍历顺序
+    Smallest, // token数少的优先
+}
+
+/// 执行pack子命令，将-f文件和-p路径下的有效文本文件合并为一个文档，总token数不超过--budget，写入-o指定的文件或输出到终端
+pub fn pack_files(paras: &ParsedParas, pack: &ParsedPack) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    // 获取所有要打包的文本，(路径, 用于--priority匹配的相对路径, 文本)
+    let mut sources: Vec<(PathBuf, PathBuf, String)> = vec![];
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取或不是有效文本而没有打包的文件及原因
+    if let Some(files) = &paras.files {
+        for f in files {
+            let file_token = match file_len(f).and_then(|size| if size > paras.options.max_size { Ok(Err(size)) } else { FileToken::new(f, size).map(Ok) }) {
+                Ok(Ok(t)) => t,
+                Ok(Err(size)) => {
+                    skipped.push((f.clone(), format!("over size limit, {} bytes > {}", size, size_limit_str(paras.options.max_size))));
+                    continue
+                },
+                Err(e) => { // 无法读取的文件
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            match file_token.with_encoding(paras.options.charset).with_binary(paras.options.binary).text_or_reason() {
+                Ok(text) => sources.push((f.clone(), f.clone(), text)),
+                Err(reason) => skipped.push((f.clone(), reason)),
+            }
+        }
+    }
+    if let Some(p) = &paras.path {
+        for (rltv_path, text) in text_files(p, &paras.options, &mut skipped)? {
+            let match_path = rltv_path.components().skip(1).collect::<PathBuf>(); // 去除开头的-p文件夹名
+            sources.push((rltv_path, match_path, text));
+        }
+    }
+    let (doc, report) = pack_text(&bpe, &sources, &skipped, pack, paras.options.special)?;
+    match &pack.outfile {
+        Some(o) => {
+            write(o, &doc).map_err(|e| MyError::WriteFileError{file: o.display().to_string(), error: e})?;
+            println!("{}", report.join("\n"));
+        },
+        None => {
+            println!("{}", doc);
+            eprintln!("{}", report.join("\n"));
+        },
+    }
+    Ok(())
+}
+
+/// 将(路径, 用于--priority匹配的相对路径, 文本)合并为总token数不超过--budget的文档，返回文档和报告
+fn pack_text(bpe: &dyn Tokenizer, sources: &[(PathBuf, PathBuf, String)], skipped: &[(PathBuf, String)], pack: &ParsedPack, special: SpecialTokens) -> Result<(String, Vec<String>), MyError> {
+    // 每个文件转为section并计算token数
+    let sections: Vec<(String, usize)> = sources.iter().map(|(path, _, text)| {
+        let section = format_section(path, text, pack.format);
+        let tokens = count(bpe, &section, special);
+        (section, tokens)
+    }).collect();
+    // 按--priority和--order确定选择顺序
+    let priority: Vec<GlobMatcher> = pack.priority.iter().map(|g| Glob::new(g).map(|g| g.compile_matcher()).map_err(|e| MyError::GlobError{glob: g.to_string(), error: e})).collect::<Result<_, _>>()?;
+    let rank = |i: usize| -> usize {
+        let (path, match_path, _) = &sources[i];
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        priority.iter().position(|g| g.is_match(match_path) || g.is_match(name)).unwrap_or(priority.len())
+    };
+    let mut order: Vec<usize> = (0..sources.len()).collect();
+    match pack.order {
+        PackOrder::Path => order.sort_by_key(|&i| (rank(i), i)),
+        PackOrder::Smallest => order.sort_by_key(|&i| (rank(i), sections[i].1, i)),
+    }
+    // 依次选择文件，加入后超出预算则跳过，每个section之间用换行连接
+    let sep = count(bpe, "\n", special);
+    let mut picked: Vec<usize> = vec![]; // 按选择顺序
+    let mut total = 0; // 已选择文件的token数之和
+    for &i in &order {
+        let tokens = sections[i].1 + if picked.is_empty() { 0 } else { sep };
+        if pack.budget == 0 || total + tokens <= pack.budget {
+            total += tokens;
+            picked.push(i);
+        }
+    }
+    // 按遍历顺序合并，整体计算token数，section连接处的token可能与单独计算的不同
+    // 仍超出预算则按选择顺序从后往前去除文件，直到去除的token数不少于超出的部分，再重新检查
+    let (doc, tokens) = loop {
+        let mut in_doc = picked.clone();
+        in_doc.sort();
+        let doc = in_doc.iter().map(|&i| sections[i].0.as_str()).collect::<Vec<&str>>().join("\n");
+        let tokens = count(bpe, &doc, special);
+        if pack.budget == 0 || tokens <= pack.budget || picked.is_empty() {
+            break (doc, tokens)
+        }
+        let mut over = tokens - pack.budget;
+        while let Some(i) = picked.pop() {
+            let removed = sections[i].1 + sep;
+            if removed >= over {
+                break
+            }
+            over -= removed;
+        }
+    };
+    // 报告打包和丢弃的文件
+    let mut report: Vec<String> = vec![];
+    if pack.budget == 0 {
+        report.push(format!("packed {} files, {} tokens", picked.len(), tokens));
+    } else {
+        report.push(format!("packed {} files, {} tokens (budget {})", picked.len(), tokens, pack.budget));
+    }
+    let dropped: Vec<usize> = (0..sources.len()).filter(|i| !picked.contains(i)).collect();
+    if !dropped.is_empty() || !skipped.is_empty() {
+        report.push(format!("dropped {} files:", dropped.len() + skipped.len()));
+        for i in dropped {
+            report.push(format!("  {} ({} tokens)", sources[i].0.display(), sections[i].1));
+        }
+        for (path, reason) in skipped {
+            report.push(format!("  {} ({})", path.display(), reason));
+        }
+    }
+    Ok((doc, report))
+}
+
+/// 将单个文件转为指定格式的section
+fn format_section(path: &Path, text: &str, format: PackFormat) -> String {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    match format {
+        PackFormat::Markdown => {
+            // 代码块的反引号数量要多于文件中最长的连续反引号
+            let longest = text.split(|c| c != '`').map(|s| s.len()).max().unwrap_or(0);
+            let fence = "`".repeat(longest.max(2) + 1);
+            let lang = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+            format!("## {}\n\n{}{}\n{}\n{}\n", path.display(), fence, lang, text, fence)
+        },
+        PackFormat::Xml => format!("<file path=\"{}\">\n{}\n</file>\n", path.display().to_string().replace('"', "&quot;"), text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    fn sources(files: &[(&str, &str)]) -> Vec<(PathBuf, PathBuf, String)> {
+        files.iter().map(|(p, t)| (PathBuf::from(p), PathBuf::from(p), t.to_string())).collect()
+    }
+
+    fn parsed(budget: usize, order: PackOrder, priority: &[&str]) -> ParsedPack {
+        ParsedPack{budget, format: PackFormat::Markdown, order, priority: priority.iter().map(|g| g.to_string()).collect(), outfile: None}
+    }
+
+    fn section_tokens(path: &str, text: &str) -> usize {
+        count(test_tokenizer(), &format_section(Path::new(path), text, PackFormat::Markdown), SpecialTokens::Allow)
+    }
+
+    #[test]
+    fn priority_first() {
+        let src = sources(&[("a.txt", "alpha beta gamma"), ("b.md", "delta epsilon"), ("src/c.rs", "fn main() {}")]);
+        // 预算只够一个文件，匹配--priority的c.rs优先
+        let budget = section_tokens("src/c.rs", "fn main() {}");
+        let (doc, report) = pack_text(test_tokenizer(), &src, &[], &parsed(budget, PackOrder::Path, &["*.rs"]), SpecialTokens::Allow).unwrap();
+        assert!(doc.starts_with("## src/c.rs\n"));
+        assert_eq!(report[0], format!("packed 1 files, {} tokens (budget {})", budget, budget));
+        // 多个glob按指定顺序
+        let (doc, _) = pack_text(test_tokenizer(), &src, &[], &parsed(0, PackOrder::Path, &["*.md", "*.txt"]), SpecialTokens::Allow).unwrap();
+        assert_eq!(doc.matches("## ").count(), 3);
+        let (doc, _) = pack_text(test_tokenizer(), &src, &[], &parsed(section_tokens("b.md", "delta epsilon"), PackOrder::Path, &["*.md", "*.txt"]), SpecialTokens::Allow).unwrap();
+        assert!(doc.starts_with("## b.md\n"));
+    }
+
+    #[test]
+    fn budget() {
+        let long = "word ".repeat(200);
+        let src = sources(&[("a.txt", &long), ("b.txt", "short"), ("c.txt", "tiny")]);
+        let small = section_tokens("b.txt", "short") + section_tokens("c.txt", "tiny") + count(test_tokenizer(), "\n", SpecialTokens::Allow);
+        // 按遍历顺序，a.txt超出预算被跳过，继续选择后面的文件
+        let (doc, report) = pack_text(test_tokenizer(), &src, &[], &parsed(small, PackOrder::Path, &[]), SpecialTokens::Allow).unwrap();
+        assert!(!doc.contains("## a.txt"));
+        assert!(doc.contains("## b.txt") && doc.contains("## c.txt"));
+        assert!(count(test_tokenizer(), &doc, SpecialTokens::Allow) <= small);
+        assert_eq!(report[0], format!("packed 2 files, {} tokens (budget {})", count(test_tokenizer(), &doc, SpecialTokens::Allow), small));
+        // 不限制预算时全部打包，文档按遍历顺序
+        let (doc, report) = pack_text(test_tokenizer(), &src, &[], &parsed(0, PackOrder::Smallest, &[]), SpecialTokens::Allow).unwrap();
+        assert!(doc.find("## a.txt").unwrap() < doc.find("## b.txt").unwrap());
+        assert_eq!(report, vec![format!("packed 3 files, {} tokens", count(test_tokenizer(), &doc, SpecialTokens::Allow))]);
+        // 预算小于任何文件时为空文档
+        let (doc, report) = pack_text(test_tokenizer(), &src, &[], &parsed(1, PackOrder::Smallest, &[]), SpecialTokens::Allow).unwrap();
+        assert_eq!(doc, "");
+        assert_eq!(report[0], "packed 0 files, 0 tokens (budget 1)");
+    }
+
+    #[test]
+    fn dropped_report() {
+        let src = sources(&[("a.txt", &"word ".repeat(200)), ("b.txt", "short")]);
+        let skipped = vec![(PathBuf::from("c.bin"), "binary file: NUL byte at offset 0".to_string())];
+        let budget = section_tokens("b.txt", "short");
+        let (_, report) = pack_text(test_tokenizer(), &src, &skipped, &parsed(budget, PackOrder::Smallest, &[]), SpecialTokens::Allow).unwrap();
+        assert_eq!(report, vec![
+            format!("packed 1 files, {} tokens (budget {})", budget, budget),
+            "dropped 2 files:".to_string(),
+            format!("  a.txt ({} tokens)", section_tokens("a.txt", &"word ".repeat(200))),
+            "  c.bin (binary file: NUL byte at offset 0)".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn invalid_glob() {
+        let src = sources(&[("a.txt", "alpha")]);
+        assert!(matches!(pack_text(test_tokenizer(), &src, &[], &parsed(0, PackOrder::Path, &["a[.txt"]), SpecialTokens::Allow), Err(MyError::GlobError{..})));
+    }
+}
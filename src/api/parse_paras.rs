@@ -1,8 +1,20 @@
 use std::path::PathBuf;
 
 use argh::FromArgs;
+use encoding_rs::Encoding;
 
-use crate::error::MyError;
+use crate::{
+    binary::{BinaryClassifier, BinaryDetection},
+    chunk::Boundary,
+    counter::Options,
+    error::MyError,
+    extract::ExtractorRegistry,
+    image::ImageDetail,
+    normalize::Normalize,
+    pack::{PackFormat, PackOrder},
+    token::SpecialTokens,
+    truncate::Strategy,
+};
 
 #[derive(FromArgs)]
 /// calculate token number
@@ -19,6 +31,18 @@ struct Paras {
     #[argh(option, short = 'p')]
     path: Option<String>,
 
+    /// only include files under -p matching these globs, matched against the path relative to -p and the file name, e.g. *.rs,src/**
+    #[argh(option, short = 'i')]
+    include: Option<String>,
+
+    /// exclude files and directories under -p matching these globs, matched against the path relative to -p and the file name, e.g. *.lock,target
+    #[argh(option, short = 'x')]
+    exclude: Option<String>,
+
+    /// JSON file of tool/function definitions, estimate the tokens they add to a prompt, per tool and in total
+    #[argh(option)]
+    tools: Option<String>,
+
     /// encoding, support: o200k_base(GPT-4o models, o1 models), cl100k_base(ChatGPT models, text-embedding-ada-002), p50k_base(Code models, text-davinci-002, text-davinci-003), p50k_edit(edit models, text-davinci-edit-001, code-davinci-edit-001), r50k_base(GPT-3 models, davinci), default: o200k_base
     #[argh(option, short = 'e')]
     encoding: Option<String>,
@@ -35,9 +59,213 @@ struct Paras {
     #[argh(option, short = 'T')]
     token_hight: Option<usize>,
 
+    /// how special tokens such as <|endoftext|> in the text are handled, support: allow(count as one special token), disallow(flag files and strings containing them with their locations), text(count as plain text), default: allow
+    #[argh(option)]
+    special_tokens: Option<String>,
+
+    /// PNG/JPEG/WebP/GIF images are counted as vision input tokens, support: low(85 tokens per image), high(170 tokens per 512px tile + 85), default: high
+    #[argh(option)]
+    image_detail: Option<String>,
+
+    /// how binary files are detected, support: off(treat all files as text), fast(signatures of common binary formats and any NUL byte in the sample), thorough(signatures, and NUL and control character ratios of the sample), default: fast
+    #[argh(option)]
+    binary_detection: Option<String>,
+
+    /// bytes at the start of each file sampled by --binary-detection, 0 means the whole file, default: 8192 for fast, 0 for thorough
+    #[argh(option)]
+    binary_sample: Option<usize>,
+
+    /// force the input encoding of text files instead of detecting it, e.g. utf-8, gbk, shift_jis, latin1, utf-16le, utf-16be, default: detect from the BOM or content
+    #[argh(option)]
+    input_encoding: Option<String>,
+
+    /// normalize the text before counting and show both raw and normalized tokens, comma-separated, support: bom(remove the BOM), crlf(CRLF to LF), trailing(strip trailing whitespace), tabs(expand tabs to spaces), blank(collapse runs of blank lines), all
+    #[argh(option)]
+    normalize: Option<String>,
+
+    /// tab width used by --normalize tabs, default: 4
+    #[argh(option)]
+    tab_width: Option<usize>,
+
+    /// open .zip, .tar, .tar.gz and .tgz archives and count their members as a subtree, -i, -x, -m, -t, -T, -d, the extractors, notebooks and the binary rules apply to each member
+    #[argh(switch)]
+    archives: bool,
+
+    /// count the visible text of .html, .htm, .xhtml files and the text extracted from .docx, .pptx, .odt, .odp, .epub, .pdf documents instead of the raw markup, and show both counts
+    #[argh(switch)]
+    extract: bool,
+
+    /// imply --extract, and show the tokens of each PDF page, PPTX slide and EPUB chapter as children of the file node
+    #[argh(switch)]
+    pages: bool,
+
+    /// also count the text outputs (stream, execute_result, display_data, error) of code cells in Jupyter notebooks
+    #[argh(switch)]
+    notebook_outputs: bool,
+
+    /// show per-symbol (function, class, impl, method, ...) tokens of Rust, Python, TypeScript/JavaScript, Go and Java files as children of the file node
+    #[argh(switch)]
+    symbols: bool,
+
+    /// split the tokens of Rust, Python, TypeScript/JavaScript, Go and Java files into code, comment, string and whitespace, and show the projected tokens without comments and with normalized whitespace
+    #[argh(switch)]
+    breakdown: bool,
+
     /// omit invalid (e.g. binary files, large files, empty files, files containing invalid characters) files from the output tree
     #[argh(switch, short = 'd')]
     valid: bool,
+
+    /// stop at the first file that cannot be read (e.g. permission denied) with an error, default: show the error status in the output and keep going
+    #[argh(switch)]
+    fail_fast: bool,
+
+    /// exit with an error after printing the results if any path could not be examined (unreadable files and directories, broken symlinks, symlink loops)
+    #[argh(switch)]
+    strict: bool,
+
+    #[argh(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum SubCommand {
+    Chunk(ChunkParas),
+    Truncate(TruncateParas),
+    Pack(PackParas),
+    Inspect(InspectParas),
+    Decode(DecodeParas),
+    Lines(LinesParas),
+    Markdown(MarkdownParas),
+    Structure(StructureParas),
+}
+
+#[derive(FromArgs)]
+/// split -f files, -s string or valid text files under -p into chunks of at most -n tokens
+#[argh(subcommand, name = "chunk")]
+struct ChunkParas {
+    /// max tokens per chunk, default: 512
+    #[argh(option, short = 'n')]
+    max_tokens: Option<usize>,
+
+    /// tokens shared by adjacent chunks, must be less than -n, default: 0
+    #[argh(option, short = 'v')]
+    overlap: Option<usize>,
+
+    /// preferred boundary to cut on, falls back to finer boundaries, support: paragraph, line, sentence, token, default: paragraph
+    #[argh(option, short = 'b')]
+    boundary: Option<String>,
+
+    /// write each chunk to a separate file in this directory
+    #[argh(option, short = 'o')]
+    outdir: Option<String>,
+
+    /// write all chunks to this JSONL file, one line per chunk with source path, byte offsets, token count and text
+    #[argh(option, short = 'j')]
+    jsonl: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// truncate a single -f file, -s string or stdin to at most -n tokens, cut at token boundaries without splitting multi-byte characters
+#[argh(subcommand, name = "truncate")]
+struct TruncateParas {
+    /// max tokens to keep, including the marker
+    #[argh(option, short = 'n')]
+    max_tokens: usize,
+
+    /// which part to keep, support: head, tail, middle(keep head and tail, drop the middle), default: head
+    #[argh(option, short = 'k')]
+    keep: Option<String>,
+
+    /// marker inserted where text was dropped, {n} is replaced by the number of dropped tokens, default: empty for head and tail, "\n...\n" for middle
+    #[argh(option)]
+    marker: Option<String>,
+
+    /// write the truncated text to this file instead of stdout
+    #[argh(option, short = 'o')]
+    outfile: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// concatenate -f files and valid text files under -p (filtered by -i and -x) into one prompt document under a token budget
+#[argh(subcommand, name = "pack")]
+struct PackParas {
+    /// max tokens of the whole document, files that do not fit are dropped and reported, 0 means unlimit, default: 0
+    #[argh(option, short = 'n')]
+    budget: Option<usize>,
+
+    /// section format of each file, support: markdown(## path + code block), xml(<file path="...">...</file>), default: markdown
+    #[argh(option, short = 'F')]
+    format: Option<String>,
+
+    /// order to pick files within the budget, support: path(traversal order), smallest(fewest tokens first), default: path
+    #[argh(option, short = 'r')]
+    order: Option<String>,
+
+    /// files matching these globs are picked first, in the given order, e.g. README.md,src/**
+    #[argh(option, short = 'y')]
+    priority: Option<String>,
+
+    /// write the document to this file instead of stdout
+    #[argh(option, short = 'o')]
+    outfile: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// show each token's ID, byte length and decoded text of -s string and -f files
+#[argh(subcommand, name = "inspect")]
+struct InspectParas {
+    /// print the text with alternating background colors marking token boundaries instead of one token per line
+    #[argh(switch, short = 'c')]
+    color: bool,
+}
+
+#[derive(FromArgs)]
+/// decode token IDs back to text with the -e encoding, read from stdin if no IDs are given
+#[argh(subcommand, name = "decode")]
+struct DecodeParas {
+    /// token IDs separated by comma or whitespace, or a JSON array, e.g. 791,11564 or "[791, 11564]"
+    #[argh(positional)]
+    ids: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// per-line token heatmap of -f files: line number, tokens and a bar chart, with the costliest spans highlighted
+#[argh(subcommand, name = "lines")]
+struct LinesParas {
+    /// merge every -l lines into one block, default: 1
+    #[argh(option, short = 'l')]
+    block: Option<usize>,
+
+    /// highlight and list the heaviest spans, 0 means none, default: 5
+    #[argh(option)]
+    top_lines: Option<usize>,
+
+    /// width of the longest bar, default: 40
+    #[argh(option, short = 'w')]
+    width: Option<usize>,
+
+    /// highlight the heaviest spans with color instead of only marking them with *
+    #[argh(switch, short = 'c')]
+    color: bool,
+}
+
+#[derive(FromArgs)]
+/// per-section token tree of -f Markdown files, with separate prose, code block and table subtotals
+#[argh(subcommand, name = "markdown")]
+struct MarkdownParas {
+    /// deepest heading level shown in the tree, deeper sections are merged into their parent, 1-6, default: 6
+    #[argh(option, short = 'l')]
+    depth: Option<usize>,
+}
+
+#[derive(FromArgs)]
+/// per-key-path token tree of -f JSON or YAML (.yaml, .yml) files, array elements are merged, with the tokens saved by minifying
+#[argh(subcommand, name = "structure")]
+struct StructureParas {
+    /// deepest level shown in the tree, deeper keys are merged into their parent, default: unlimited
+    #[argh(option, short = 'l')]
+    depth: Option<usize>,
 }
 
 /// 存储解析后的命令行参数
@@ -46,55 +274,129 @@ pub struct ParsedParas {
     pub files:        Option<Vec<PathBuf>>, // 要计算token的文件，多个之间逗号间隔
     pub string:       Option<String>,       // 要计算token的字符串
     pub path:         Option<PathBuf>,      // 要递归的路径，程序会递归计算该路径下每个文件（自动排除二进制文件和大小超过10M的文件）的token数，并以tree的形式打印
+    pub tools:        Option<PathBuf>,      // tool/function定义的JSON文件，计算这些定义加入prompt后的token数
     pub encoding:     String,               // 编码集，默认o200k_base
-    pub max_size:     u64,                  // 指定文件大小上限，大小>-m的文件不计算token，但会包含在打印的tree中，支持4种单位b、k(1024b)、m(1024k)、g(1024m)，大小写都行，例如：15b、500k、200m、4g，默认10m，0表示无限制（此时单位无所谓）
-    pub max_size_str: String,               // 指定文件大小上限的原始参数
-    pub min_token:    usize,                // 指定token数下限，token数<-t的文件不会包含在打印的tree中，只输出token数在[-t, -T]范围内的文件，默认0
-    pub max_token:    usize,                // 指定token数上限，token数>-T的文件不会包含在打印的tree中，0表示不限制，只输出token数在[-t, -T]范围内的文件，默认0
-    pub only_valid:   bool,                 // 仅输出有效文件结果，二进制文件、大小超过-m的文件、含有非UTF-8字符的文件、空文件，将不会包含在打印结果中
+    pub options:      Options,              // 计算token的设置，文件大小上限、token数范围、-i、-x过滤、文本处理方式等
+    pub strict:       bool,                 // 有无法检查的路径时，输出结果后以错误退出
+    pub command:      Option<Command>,      // 子命令，None表示计算token数
+}
+
+/// 解析后的子命令
+#[derive(Debug)]
+pub enum Command {
+    Chunk(ParsedChunk),         // 将文本切分为token数不超过上限的chunk
+    Truncate(ParsedTruncate),   // 将文本截断至token数上限以内
+    Pack(ParsedPack),           // 将多个文件合并为一个不超过token预算的文档
+    Inspect(ParsedInspect),     // 显示每个token的ID、byte数和解码后的文本
+    Decode(ParsedDecode),       // 将token ID解码为文本
+    Lines(ParsedLines),         // 每行的token数
+    Markdown(ParsedMarkdown),   // Markdown每个章节的token数
+    Structure(ParsedStructure), // JSON、YAML每个key路径的token数
+}
+
+/// 存储解析后的chunk子命令参数
+#[derive(Debug)]
+pub struct ParsedChunk {
+    pub max_tokens: usize,            // 每个chunk的token数上限，默认512
+    pub overlap:    usize,            // 相邻chunk重叠的token数，默认0
+    pub boundary:   Boundary,         // 优先在哪种边界切分，找不到时依次尝试更细的边界，默认paragraph
+    pub outdir:     Option<PathBuf>,  // 每个chunk写入该路径下的单独文件
+    pub jsonl:      Option<PathBuf>,  // 所有chunk写入该JSONL文件，-o和-j都没指定则JSONL输出到终端
+}
+
+/// 存储解析后的truncate子命令参数
+#[derive(Debug)]
+pub struct ParsedTruncate {
+    pub max_tokens: usize,           // 截断后的token数上限，包含marker
+    pub strategy:   Strategy,        // 保留开头、结尾还是开头和结尾，默认head
+    pub marker:     String,          // 插入到删除位置的标记，`{n}`会替换为删除的token数，head和tail默认为空，middle默认为"\n...\n"
+    pub outfile:    Option<PathBuf>, // 截断后的文本写入该文件，没指定则输出到终端
+}
+
+/// 存储解析后的pack子命令参数
+#[derive(Debug)]
+pub struct ParsedPack {
+    pub budget:   usize,           // 整个文档的token数上限，0表示不限制
+    pub format:   PackFormat,      // 每个文件的格式，默认markdown
+    pub order:    PackOrder,       // 超出预算时优先选择哪些文件，默认按遍历顺序
+    pub priority: Vec<String>,     // 匹配这些glob的文件按指定顺序优先选择
+    pub outfile:  Option<PathBuf>, // 文档写入该文件，没指定则输出到终端
+}
+
+/// 存储解析后的inspect子命令参数
+#[derive(Debug)]
+pub struct ParsedInspect {
+    pub color: bool, // 用交替的背景色显示token边界
+}
+
+/// 存储解析后的decode子命令参数
+#[derive(Debug)]
+pub struct ParsedDecode {
+    pub ids: Option<String>, // 要解码的token ID，没指定则从标准输入读取
+}
+
+/// 存储解析后的lines子命令参数
+#[derive(Debug)]
+pub struct ParsedLines {
+    pub block: usize, // 每多少行合并为一个块，默认1
+    pub top:   usize, // 高亮并列出token数最多的块数，默认5
+    pub width: usize, // 最长条形图的宽度，默认40
+    pub color: bool,  // 用颜色高亮token数最多的块
+}
+
+/// 存储解析后的markdown子命令参数
+#[derive(Debug)]
+pub struct ParsedMarkdown {
+    pub depth: usize, // 显示的最深标题级别，更深的章节合并到父章节中，默认6
+}
+
+/// 存储解析后的structure子命令参数
+#[derive(Debug)]
+pub struct ParsedStructure {
+    pub depth: usize, // 显示的最深层级，更深的key合并到父节点中，默认不限制
 }
 
 /// 解析参数
 pub fn parse_para() -> Result<ParsedParas, MyError> {
     let para: Paras = argh::from_env();
     // 解析文件大小上限
-    let (max_size, max_size_str) = match para.max_size { // 指定文件大小上限，大小>-m的文件不计算token，支持4种单位b、k(1024b)、m(1024k)、g(1024m)，大小写都行，例如：15b、500k、200m、4g，默认10m，0表示无限制（此时单位无所谓）
+    let max_size = match para.max_size { // 指定文件大小上限，大小>-m的文件不计算token，支持4种单位b、k(1024b)、m(1024k)、g(1024m)，大小写都行，例如：15b、500k、200m、4g，默认10m，0表示无限制（此时单位无所谓）
         Some(m) => {
             let mut para_size = m.to_lowercase();
             match para_size.pop() {
                 Some(p) => match para_size.parse::<u64>() { // 这里p是指定参数的最后一个字符
                     Ok(n) => match p { // 这里n是指定参数的数值
                         'b' => if n == 0 {
-                            (u64::MAX, format!("{}Gb", u64::MAX/1024/1024/1024))
+                            u64::MAX
                         } else {
-                            (n, format!("{n} bytes"))
+                            n
                         },
                         'k' => if n == 0 {
-                            (u64::MAX, format!("{}Gb", u64::MAX/1024/1024/1024))
+                            u64::MAX
                         } else {
-                            (n*1024, format!("{n}Kb"))
+                            n*1024
                         },
                         'm' => if n == 0 {
-                            (u64::MAX, format!("{}Gb", u64::MAX/1024/1024/1024))
+                            u64::MAX
                         } else {
-                            (n*1024*1024, format!("{n}Mb"))
+                            n*1024*1024
                         },
                         'g' => if n == 0 {
-                            (u64::MAX, format!("{}Gb", u64::MAX/1024/1024/1024))
+                            u64::MAX
                         } else {
-                            (n*1024*1024*1024, format!("{n}Gb"))
+                            n*1024*1024*1024
                         },
                         _ => return Err(MyError::ParaError{para: format!("-m suffix only support b, k, m, g, not {}", p)}),
                     },
                     Err(e) => return Err(MyError::ParseStringError{from: m.to_string(), to: "u64".to_string(), error: e}),
                 },
-                None => (10485760, "10Mb".to_string()), // 10M=10*1024*1024=10485760
+                None => 10485760, // 10M=10*1024*1024=10485760
             }
         },
-        None => (10485760, "10Mb".to_string()), // 10M=10*1024*1024=10485760
+        None => 10485760, // 10M=10*1024*1024=10485760
     };
     // 其他参数
-    let out: ParsedParas = ParsedParas{
+    let mut out: ParsedParas = ParsedParas{
         files: match para.files { // 要计算token的文件，多个之间逗号间隔
             Some(f) => {
                 let mut tmp_files: Vec<PathBuf> = vec![];
@@ -120,6 +422,16 @@ pub fn parse_para() -> Result<ParsedParas, MyError> {
             },
             None => None,
         },
+        tools: match para.tools { // tool/function定义的JSON文件，计算这些定义加入prompt后的token数
+            Some(t) => {
+                let tmp_file = PathBuf::from(&t);
+                if !(tmp_file.exists() && tmp_file.is_file()) {
+                    return Err(MyError::FileNotExistError{file: t})
+                }
+                Some(tmp_file)
+            },
+            None => None,
+        },
         encoding: match para.encoding { // 编码集，默认o200k_base
             Some(e) => {
                 if ["o200k_base", "cl100k_base", "p50k_base", "p50k_edit", "r50k_base"].iter().any(|x| x == &e) {
@@ -130,25 +442,166 @@ pub fn parse_para() -> Result<ParsedParas, MyError> {
             },
             None => "o200k_base".to_string(),
         },
-        max_size, // 指定文件大小上限，大小>-m的文件不计算token，支持4种单位b、k(1024b)、m(1024k)、g(1024m)，大小写都行，例如：15b、500k、200m、4g，默认10m，0表示无限制（此时单位无所谓）
-        max_size_str, // 指定文件大小上限的原始参数
-        min_token: match para.token_low { // 指定token数下限，token数<-t的文件不会包含在打印的tree中，只输出token数在[-t, -T]范围内的文件，默认0
-            Some(t) => t,
-            None => 0,
+        options: Options{ // 计算token的设置
+            include: match para.include { // -p遍历时只包含匹配这些glob的文件，多个之间逗号间隔
+                Some(i) => i.split(",").map(|g| g.to_string()).collect(),
+                None => vec![],
+            },
+            exclude: match para.exclude { // -p遍历时排除匹配这些glob的文件和文件夹，多个之间逗号间隔
+                Some(x) => x.split(",").map(|g| g.to_string()).collect(),
+                None => vec![],
+            },
+            max_size, // 指定文件大小上限，大小>-m的文件不计算token，支持4种单位b、k(1024b)、m(1024k)、g(1024m)，大小写都行，例如：15b、500k、200m、4g，默认10m，0表示无限制（此时单位无所谓）
+            min_token: para.token_low.unwrap_or_default(), // 指定token数下限，token数<-t的文件不会包含在打印的tree中，只输出token数在[-t, -T]范围内的文件，默认0
+            max_token: match para.token_hight { // 指定token数上限，token数>-T的文件不会包含在打印的tree中，0表示不限制，只输出token数在[-t, -T]范围内的文件，默认0
+                Some(t) => if t == 0 {
+                    usize::MAX
+                } else {
+                    t
+                },
+                None => usize::MAX,
+            },
+            special: match para.special_tokens.as_deref() { // 文本中特殊token的处理方式，allow、disallow或text，默认allow
+                Some("allow") | None => SpecialTokens::Allow,
+                Some("disallow") => SpecialTokens::Disallow,
+                Some("text") => SpecialTokens::Text,
+                Some(s) => return Err(MyError::ParaError{para: format!("--special-tokens only support allow, disallow, text, not: {}", s)}),
+            },
+            image_detail: match para.image_detail.as_deref() { // 图片作为vision输入计算token的方式，low或high，默认high
+                Some("low") => ImageDetail::Low,
+                Some("high") | None => ImageDetail::High,
+                Some(d) => return Err(MyError::ParaError{para: format!("--image-detail only support low, high, not: {}", d)}),
+            },
+            binary: match para.binary_detection.as_deref() { // 二进制文件分类器，检测方式和采样byte数
+                Some("off") => BinaryClassifier{detection: BinaryDetection::Off, sample: 0},
+                Some("fast") | None => BinaryClassifier{detection: BinaryDetection::Fast, sample: para.binary_sample.unwrap_or(8192)},
+                Some("thorough") => BinaryClassifier{detection: BinaryDetection::Thorough, sample: para.binary_sample.unwrap_or_default()},
+                Some(b) => return Err(MyError::ParaError{para: format!("--binary-detection only support off, fast, thorough, not: {}", b)}),
+            },
+            charset: match para.input_encoding.as_deref() { // 指定文本文件的编码
+                Some(e) => match Encoding::for_label(e.as_bytes()) {
+                    Some(enc) => Some(enc),
+                    None => return Err(MyError::ParaError{para: format!("--input-encoding unknown encoding: {}", e)}),
+                },
+                None => None,
+            },
+            normalize: match para.normalize.as_deref() { // 计算token前对文本的规范化处理
+                Some(n) => match para.tab_width {
+                    Some(0) => return Err(MyError::ParaError{para: "--tab-width must be greater than 0".to_string()}),
+                    w => Normalize::parse(n, w.unwrap_or(4)).map_err(|i| MyError::ParaError{para: format!("--normalize only support bom, crlf, trailing, tabs, blank, all, not: {}", i)})?,
+                },
+                None => Normalize::default(),
+            },
+            archives: para.archives, // 打开压缩包，计算其中每个文件的token数
+            extractors: ExtractorRegistry::default(), // 文本提取器，--extract时在下面添加内置提取器
+            pages: para.pages, // 在文件节点下显示PDF每页、PPTX每张幻灯片、EPUB每章的token数
+            notebook_outputs: para.notebook_outputs, // .ipynb文件同时计算code cell的文本输出
+            symbols: para.symbols, // 在文件节点下显示每个函数、类、impl等的token数
+            breakdown: para.breakdown, // 将源代码文件的token数分为代码、注释、字符串、空白
+            only_valid: para.valid, // 仅输出有效文件结果，二进制文件、大小超过-m的文件、含有非UTF-8字符的文件、空文件，将不会包含在打印结果中
+            fail_fast: para.fail_fast, // 遇到无法读取的文件时立即报错退出，默认显示错误状态并继续
         },
-        max_token: match para.token_hight { // 指定token数上限，token数>-T的文件不会包含在打印的tree中，0表示不限制，只输出token数在[-t, -T]范围内的文件，默认0
-            Some(t) => if t == 0 {
-                usize::MAX
-            } else {
-                t
+        strict: para.strict, // 有无法检查的路径时，输出结果后以错误退出
+        command: match para.command { // 子命令
+            Some(SubCommand::Chunk(c)) => {
+                let max_tokens = c.max_tokens.unwrap_or(512);
+                let overlap = c.overlap.unwrap_or_default();
+                if max_tokens == 0 {
+                    return Err(MyError::ParaError{para: "chunk -n must be greater than 0".to_string()})
+                }
+                if overlap >= max_tokens {
+                    return Err(MyError::ParaError{para: format!("chunk -v {} must be less than -n {}", overlap, max_tokens)})
+                }
+                Some(Command::Chunk(ParsedChunk{
+                    max_tokens,
+                    overlap,
+                    boundary: match c.boundary.as_deref() {
+                        Some("paragraph") | None => Boundary::Paragraph,
+                        Some("line") => Boundary::Line,
+                        Some("sentence") => Boundary::Sentence,
+                        Some("token") => Boundary::Token,
+                        Some(b) => return Err(MyError::ParaError{para: format!("chunk -b only support paragraph, line, sentence, token, not: {}", b)}),
+                    },
+                    outdir: c.outdir.map(PathBuf::from),
+                    jsonl: c.jsonl.map(PathBuf::from),
+                }))
             },
-            None => usize::MAX,
+            Some(SubCommand::Truncate(t)) => {
+                let strategy = match t.keep.as_deref() {
+                    Some("head") | None => Strategy::Head,
+                    Some("tail") => Strategy::Tail,
+                    Some("middle") => Strategy::Middle,
+                    Some(k) => return Err(MyError::ParaError{para: format!("truncate -k only support head, tail, middle, not: {}", k)}),
+                };
+                Some(Command::Truncate(ParsedTruncate{
+                    max_tokens: t.max_tokens,
+                    strategy,
+                    marker: match t.marker {
+                        Some(m) => m.replace("\\n", "\n"), // 命令行中的`\n`转为换行
+                        None if strategy == Strategy::Middle => "\n...\n".to_string(),
+                        None => "".to_string(),
+                    },
+                    outfile: t.outfile.map(PathBuf::from),
+                }))
+            },
+            Some(SubCommand::Pack(p)) => Some(Command::Pack(ParsedPack{
+                budget: p.budget.unwrap_or_default(),
+                format: match p.format.as_deref() {
+                    Some("markdown") | None => PackFormat::Markdown,
+                    Some("xml") => PackFormat::Xml,
+                    Some(f) => return Err(MyError::ParaError{para: format!("pack -F only support markdown, xml, not: {}", f)}),
+                },
+                order: match p.order.as_deref() {
+                    Some("path") | None => PackOrder::Path,
+                    Some("smallest") => PackOrder::Smallest,
+                    Some(o) => return Err(MyError::ParaError{para: format!("pack -r only support path, smallest, not: {}", o)}),
+                },
+                priority: match p.priority {
+                    Some(y) => y.split(",").map(|g| g.to_string()).collect(),
+                    None => vec![],
+                },
+                outfile: p.outfile.map(PathBuf::from),
+            })),
+            Some(SubCommand::Inspect(i)) => Some(Command::Inspect(ParsedInspect{color: i.color})),
+            Some(SubCommand::Decode(d)) => Some(Command::Decode(ParsedDecode{ids: d.ids})),
+            Some(SubCommand::Lines(l)) => Some(Command::Lines(ParsedLines{
+                block: match l.block {
+                    Some(0) => return Err(MyError::ParaError{para: "lines -l must be greater than 0".to_string()}),
+                    Some(b) => b,
+                    None => 1,
+                },
+                top: l.top_lines.unwrap_or(5),
+                width: l.width.unwrap_or(40),
+                color: l.color,
+            })),
+            Some(SubCommand::Markdown(m)) => Some(Command::Markdown(ParsedMarkdown{
+                depth: match m.depth {
+                    Some(d) if (1..=6).contains(&d) => d,
+                    Some(d) => return Err(MyError::ParaError{para: format!("markdown -l only support 1-6, not: {}", d)}),
+                    None => 6,
+                },
+            })),
+            Some(SubCommand::Structure(s)) => Some(Command::Structure(ParsedStructure{
+                depth: s.depth.unwrap_or(usize::MAX),
+            })),
+            None => None,
         },
-        only_valid: para.valid, // 仅输出有效文件结果，二进制文件、大小超过-m的文件、含有非UTF-8字符的文件、空文件，将不会包含在打印结果中
     };
-    // -f、-s、-p必须至少指定1个
-    if out.files.is_none() && out.string.is_none() && out.path.is_none() {
-        return Err(MyError::ParaError{para: "must specify -f or -s or -p".to_string()});
+    // --extract、--pages使用内置的文本提取器，HTML计算时按--input-encoding解码
+    if para.extract || para.pages {
+        out.options.extractors.register_builtin();
+    }
+    // -f、-s、-p、--tools必须至少指定1个，truncate可以从标准输入读取，decode不需要
+    if !matches!(out.command, Some(Command::Truncate(_) | Command::Decode(_))) && out.files.is_none() && out.string.is_none() && out.path.is_none() && out.tools.is_none() {
+        return Err(MyError::ParaError{para: "must specify -f or -s or -p or --tools".to_string()});
+    }
+    // inspect只支持-f、-s
+    if matches!(out.command, Some(Command::Inspect(_))) && out.files.is_none() && out.string.is_none() {
+        return Err(MyError::ParaError{para: "inspect must specify -f or -s".to_string()});
+    }
+    // lines、markdown、structure只支持-f
+    if matches!(out.command, Some(Command::Lines(_) | Command::Markdown(_) | Command::Structure(_))) && out.files.is_none() {
+        return Err(MyError::ParaError{para: "lines, markdown and structure must specify -f".to_string()});
     }
     Ok(out)
 }
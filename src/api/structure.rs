@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use termtree::Tree;
+
+use crate::{
+    counter::skip_or_fail,
+    error::MyError,
+    parse_paras::{ParsedParas, ParsedStructure},
+    token::{
+        SpecialTokens,
+        count,
+        get_tokenizer,
+        read_text,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// JSON、YAML中一个key路径的统计，数组的所有元素合并为一个`[]`子节点
+struct Node {
+    kinds:    Vec<&'static str>,    // 值的类型，数组元素合并后可能有多种类型
+    count:    usize,                // 出现次数，数组元素合并后大于1
+    elements: usize,                // 数组元素总数
+    tokens:   usize,                // 压缩后`"key":value`的token数之和
+    children: Vec<(String, Node)>,  // 子节点，按首次出现的顺序
+}
+
+impl Node {
+    fn new() -> Self {
+        Node{kinds: vec![], count: 0, elements: 0, tokens: 0, children: vec![]}
+    }
+
+    /// 将一个值（及其所有子节点）计入该节点，key是该值在父object中的key，数组元素和根节点为None
+    /// starts是压缩后JSON每个token的起始byte位置，pos是该值（包括`"key":`）在压缩后JSON中的起始位置，返回后为结束位置
+    /// 起始位置在`"key":value`范围内的token计入该节点，只需遍历一次，不必重新编码每个节点
+    fn add(&mut self, starts: &[usize], key: Option<&str>, value: &Value, pos: &mut usize) {
+        let start = *pos;
+        if let Some(k) = key {
+            *pos += Value::from(k).to_string().len() + 1; // `"key":`
+        }
+        self.count += 1;
+        let kind = match value {
+            Value::Object(_) => "object",
+            Value::Array(_) => "array",
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Null => "null",
+        };
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        match value {
+            Value::Object(o) => {
+                *pos += 1; // `{`
+                for (i, (k, v)) in o.iter().enumerate() {
+                    *pos += usize::from(i > 0); // `,`
+                    self.child(k).add(starts, Some(k), v, pos);
+                }
+                *pos += 1; // `}`
+            },
+            Value::Array(a) => {
+                self.elements += a.len();
+                *pos += 1; // `[`
+                for (i, v) in a.iter().enumerate() {
+                    *pos += usize::from(i > 0); // `,`
+                    self.child("[]").add(starts, None, v, pos);
+                }
+                *pos += 1; // `]`
+            },
+            _ => *pos += value.to_string().len(),
+        }
+        self.tokens += starts.partition_point(|&s| s < *pos) - starts.partition_point(|&s| s < start);
+    }
+
+    /// 获取指定名称的子节点，不存在则插入
+    fn child(&mut self, name: &str) -> &mut Node {
+        let pos = match self.children.iter().position(|(n, _)| n == name) {
+            Some(p) => p,
+            None => {
+                self.children.push((name.to_string(), Node::new()));
+                self.children.len() - 1
+            },
+        };
+        &mut self.children[pos].1
+    }
+
+    /// 递归创建tree，depth为剩余可显示的层数
+    fn tree(&self, name: String, depth: usize) -> Tree<String> {
+        let kind = self.kinds.join("|");
+        let label = match (self.count > 1, self.kinds == ["array"]) {
+            (true, true) => format!("{} ({} ×{}, {} elements in total, {} tokens)", name, kind, self.count, self.elements, self.tokens),
+            (true, false) => format!("{} ({} ×{}, {} tokens)", name, kind, self.count, self.tokens),
+            (false, true) => format!("{} ({} of {} elements, {} tokens)", name, kind, self.elements, self.tokens),
+            (false, false) => format!("{} ({}, {} tokens)", name, kind, self.tokens),
+        };
+        let mut tree = Tree::new(label);
+        if depth > 0 {
+            tree = tree.with_leaves(self.children.iter().map(|(n, c)| c.tree(n.clone(), depth - 1)));
+        }
+        tree
+    }
+}
+
+/// 执行structure子命令，以tree的形式打印-f每个JSON、YAML文件每个key路径的token数，以及压缩空白后节省的token数
+pub fn structure_files(paras: &ParsedParas, structure: &ParsedStructure) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let mut skipped: Vec<(PathBuf, String)> = vec![]; // 无法读取的文件及错误状态
+    if let Some(files) = &paras.files {
+        for f in files {
+            let text = match read_text(f, &paras.options) {
+                Ok(t) => t,
+                Err(e) => { // 无法读取的文件，--fail-fast时返回错误
+                    skip_or_fail(f, e, &paras.options, &mut skipped)?;
+                    continue
+                },
+            };
+            match structure_tree(&bpe, f, &text, structure.depth, paras.options.special) {
+                Ok(tree) => println!("{}", tree),
+                Err(e) => skip_or_fail(f, e, &paras.options, &mut skipped)?, // 无法解析的文件
+            }
+        }
+    }
+    for (path, status) in &skipped {
+        eprintln!("[skip]: {} {}", path.display(), status);
+    }
+    Ok(())
+}
+
+/// 解析JSON或YAML文件的文本（根据后缀`.yaml`、`.yml`判断），返回每个key路径token数的tree字符串
+/// 每个节点的token数按压缩后的JSON（`"key":value`）计算，数组的所有元素合并为一个`[]`节点，只显示depth层
+/// 整个压缩后的JSON只编码一次，每个token按起始byte位置计入所在的节点
+pub fn structure_tree(bpe: &dyn Tokenizer, file: &Path, text: &str, depth: usize, special: SpecialTokens) -> Result<String, MyError> {
+    let is_yaml = file.extension().is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+    let value: Value = if is_yaml {
+        yaml_to_json(serde_yaml::from_str(text).map_err(|e| MyError::ParseYamlError{file: file.display().to_string(), error: e})?)
+    } else {
+        serde_json::from_str(text).map_err(|e| MyError::ParseJsonError{file: file.display().to_string(), error: e})?
+    };
+    let minified = value.to_string();
+    let offsets = token_offsets(bpe, &minified, special);
+    let mut root = Node::new();
+    root.add(&offsets[..offsets.len() - 1], None, &value, &mut 0);
+    // 原始文件与压缩空白后的token数
+    let raw = count(bpe, text, special);
+    let minified = root.tokens;
+    let saves = if raw > minified {
+        format!("minify saves {} tokens ({:.1}%)", raw - minified, (raw - minified) as f64 * 100.0 / raw as f64)
+    } else {
+        "minify saves 0 token".to_string()
+    };
+    let mut tree = Tree::new(format!("{} (raw {} tokens, minified JSON {} tokens, {})", file.display(), raw, minified, saves));
+    tree.push(root.tree("$".to_string(), depth));
+    Ok(tree.to_string().trim_end().to_string())
+}
+
+/// 将YAML的值转为JSON的值，数字、bool、null等非字符串的key转为字符串（例如`1: a`的key为`"1"`），带tag的值只保留值
+fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, _) => Value::from(i),
+            (None, Some(u), _) => Value::from(u),
+            (None, None, Some(f)) => serde_json::Number::from_f64(f).map(Value::Number).unwrap_or_else(|| Value::String(n.to_string())), // .nan、.inf
+            _ => Value::String(n.to_string()),
+        },
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(seq) => Value::Array(seq.into_iter().map(yaml_to_json).collect()),
+        serde_yaml::Value::Mapping(map) => Value::Object(map.into_iter().map(|(k, v)| {
+            let key = match yaml_to_json(k) {
+                Value::String(s) => s,
+                other => other.to_string(), // 数字、bool、null以及作为key的数组、object
+            };
+            (key, yaml_to_json(v))
+        }).collect()),
+        serde_yaml::Value::Tagged(t) => yaml_to_json(t.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    #[test]
+    fn node_sums() {
+        let text = r#"{"name": "tokenum", "items": [{"id": 1, "tags": ["a", "b"]}, {"id": 2}], "ok": true}"#;
+        let value: Value = serde_json::from_str(text).unwrap();
+        let minified = value.to_string();
+        let offsets = token_offsets(test_tokenizer(), &minified, SpecialTokens::Allow);
+        let mut root = Node::new();
+        root.add(&offsets[..offsets.len() - 1], None, &value, &mut 0);
+        // 根节点等于整体编码的token数，子节点之和不超过父节点
+        assert_eq!(root.tokens, count(test_tokenizer(), &minified, SpecialTokens::Allow));
+        fn check(node: &Node) {
+            assert!(node.children.iter().map(|(_, c)| c.tokens).sum::<usize>() <= node.tokens);
+            node.children.iter().for_each(|(_, c)| check(c));
+        }
+        check(&root);
+        let items = root.children.iter().find(|(n, _)| n == "items").map(|(_, c)| c).unwrap();
+        let element = &items.children[0].1;
+        assert_eq!((items.elements, element.count, element.kinds.as_slice()), (2, 2, ["object"].as_slice()));
+        assert_eq!(element.children.iter().find(|(n, _)| n == "id").unwrap().1.count, 2);
+    }
+
+    #[test]
+    fn yaml_keys() {
+        let out = structure_tree(test_tokenizer(), Path::new("k.yaml"), "1: a\ntrue: b\n~: c\n2.5: !tag d\nname: e\n", 1, SpecialTokens::Allow).unwrap();
+        let keys: Vec<&str> = out.lines().skip(2).map(|l| l.trim_start_matches(['│', '├', '└', '─', ' ']).split(" (").next().unwrap()).collect();
+        assert_eq!(keys, ["1", "true", "null", "2.5", "name"]);
+        assert_eq!(yaml_to_json(serde_yaml::from_str("[1, 2]: x").unwrap()), serde_json::json!({"[1,2]": "x"}));
+        assert_eq!(yaml_to_json(serde_yaml::from_str("a: .nan").unwrap()), serde_json::json!({"a": ".nan"}));
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(matches!(structure_tree(test_tokenizer(), Path::new("a.json"), "{\"a\": 1,}", 5, SpecialTokens::Allow), Err(MyError::ParseJsonError{..})));
+        assert!(matches!(structure_tree(test_tokenizer(), Path::new("a.yml"), "a: [", 5, SpecialTokens::Allow), Err(MyError::ParseYamlError{..})));
+    }
+}
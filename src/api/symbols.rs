@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use termtree::Tree;
+use tree_sitter::{Language, Node, Parser};
+
+use crate::{
+    token::{
+        SpecialTokens,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 根据文件后缀获取tree-sitter语法，支持Rust、Python、TypeScript/JavaScript、Go、Java，其他返回None
+pub fn language(file: &Path) -> Option<Language> {
+    let ext = file.extension()?.to_string_lossy().to_lowercase();
+    Some(match ext.as_str() {
+        "rs" => tree_sitter_rust::LANGUAGE.into(),
+        "py" | "pyi" => tree_sitter_python::LANGUAGE.into(),
+        "ts" | "mts" | "cts" | "js" | "mjs" | "cjs" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        "tsx" | "jsx" => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        "go" => tree_sitter_go::LANGUAGE.into(),
+        "java" => tree_sitter_java::LANGUAGE.into(),
+        _ => return None,
+    })
+}
+
+/// 解析源代码文件，返回顶层函数、类、impl等及其内部方法的token数，作为文件节点的子节点
+/// 每个token按起始byte位置计入所在的symbol，紧挨着symbol上方的注释和属性（例如`///`、`#[derive]`）计入该symbol
+/// 不是支持的语言或解析失败则返回空Vec，有symbol时最后一个子节点是不属于任何顶层symbol的token数（import、注释等）
+pub fn symbol_tree(bpe: &dyn Tokenizer, file: &Path, text: &str, special: SpecialTokens) -> Vec<Tree<String>> {
+    let Some(lang) = language(file) else {
+        return vec![]
+    };
+    let mut parser = Parser::new();
+    if parser.set_language(&lang).is_err() {
+        return vec![]
+    }
+    let Some(tree) = parser.parse(text, None) else {
+        return vec![]
+    };
+    let offsets = token_offsets(bpe, text, special);
+    let starts = &offsets[..offsets.len() - 1]; // 每个token的起始byte位置
+    let (mut leaves, tokens) = symbols(tree.root_node(), text, starts);
+    if !leaves.is_empty() && starts.len() > tokens {
+        leaves.push(Tree::new(format!("other code ({} tokens)", starts.len() - tokens)));
+    }
+    leaves
+}
+
+/// 遍历节点的子节点，返回每个symbol的tree，以及这些symbol的token数之和
+fn symbols(parent: Node, src: &str, starts: &[usize]) -> (Vec<Tree<String>>, usize) {
+    let mut leaves: Vec<Tree<String>> = vec![];
+    let mut total = 0;
+    let mut cursor = parent.walk();
+    for node in parent.named_children(&mut cursor) {
+        // Java枚举中的方法在enum_body_declarations中
+        if node.kind() == "enum_body_declarations" {
+            let (l, t) = symbols(node, src, starts);
+            leaves.extend(l);
+            total += t;
+            continue
+        }
+        if let Some((label, body)) = symbol(node, src) {
+            let start = leading_comments(node, src);
+            let tokens = starts.partition_point(|&s| s < node.end_byte()) - starts.partition_point(|&s| s < start);
+            let children = match body {
+                Some(b) => symbols(b, src, starts).0,
+                None => vec![],
+            };
+            leaves.push(Tree::new(format!("{} ({} tokens)", label, tokens)).with_leaves(children));
+            total += tokens;
+        }
+    }
+    (leaves, total)
+}
+
+/// 判断节点是否是symbol，是则返回(显示的名称, 包含方法的body节点)
+fn symbol<'a>(node: Node<'a>, src: &str) -> Option<(String, Option<Node<'a>>)> {
+    let field = |name: &str| node.child_by_field_name(name).map(|n| node_text(n, src)).unwrap_or_default();
+    let body = node.child_by_field_name("body");
+    let label = match node.kind() {
+        // Rust
+        "function_item" | "function_signature_item" => format!("fn {}", field("name")),
+        "struct_item" => format!("struct {}", field("name")),
+        "enum_item" => format!("enum {}", field("name")),
+        "union_item" => format!("union {}", field("name")),
+        "type_item" => format!("type {}", field("name")),
+        "const_item" => format!("const {}", field("name")),
+        "static_item" => format!("static {}", field("name")),
+        "macro_definition" => format!("macro_rules! {}", field("name")),
+        "trait_item" => return Some((format!("trait {}", field("name")), body)),
+        "mod_item" => return Some((format!("mod {}", field("name")), body)),
+        "impl_item" => {
+            let label = match node.child_by_field_name("trait") {
+                Some(t) => format!("impl {} for {}", node_text(t, src), field("type")),
+                None => format!("impl {}", field("type")),
+            };
+            return Some((label, body))
+        },
+        // Python
+        "function_definition" => format!("def {}", field("name")),
+        "class_definition" => return Some((format!("class {}", field("name")), body)),
+        "decorated_definition" => return symbol(node.child_by_field_name("definition")?, src),
+        // TypeScript/JavaScript
+        "function_declaration" if src[node.byte_range()].starts_with("func ") => format!("func {}", field("name")), // Go的函数也是function_declaration
+        "function_declaration" | "generator_function_declaration" | "function_signature" => format!("function {}", field("name")),
+        "class_declaration" | "abstract_class_declaration" => return Some((format!("class {}", field("name")), body)),
+        "interface_declaration" => return Some((format!("interface {}", field("name")), body)),
+        "type_alias_declaration" => format!("type {}", field("name")),
+        "enum_declaration" => return Some((format!("enum {}", field("name")), body)),
+        "internal_module" | "module" => return Some((format!("namespace {}", field("name")), body)),
+        "method_definition" | "method_signature" | "abstract_method_signature" => format!("method {}", field("name")),
+        "lexical_declaration" | "variable_declaration" => {
+            let mut cursor = node.walk();
+            let declarator = node.named_children(&mut cursor).find(|n| n.kind() == "variable_declarator")?;
+            let keyword = src[node.byte_range()].split_whitespace().next().unwrap_or_default();
+            format!("{} {}", keyword, declarator.child_by_field_name("name").map(|n| node_text(n, src)).unwrap_or_default())
+        },
+        "export_statement" => return symbol(node.child_by_field_name("declaration")?, src),
+        "expression_statement" if node.named_child(0)?.kind() == "internal_module" => return symbol(node.named_child(0)?, src), // `namespace X {}`
+        // Go
+        "method_declaration" if node.child_by_field_name("receiver").is_some() => format!("func {} {}", field("receiver"), field("name")),
+        "type_declaration" => {
+            let mut cursor = node.walk();
+            let spec = node.named_children(&mut cursor).find(|n| n.kind() == "type_spec" || n.kind() == "type_alias")?;
+            format!("type {}", spec.child_by_field_name("name").map(|n| node_text(n, src)).unwrap_or_default())
+        },
+        // Java
+        "method_declaration" => format!("method {}", field("name")),
+        "constructor_declaration" => format!("constructor {}", field("name")),
+        "record_declaration" => return Some((format!("record {}", field("name")), body)),
+        "annotation_type_declaration" => return Some((format!("@interface {}", field("name")), body)),
+        _ => return None,
+    };
+    Some((label, None))
+}
+
+/// 向前扩展symbol的起始位置，包含紧挨着的注释和属性
+fn leading_comments(node: Node, src: &str) -> usize {
+    let mut start = node.start_byte();
+    let mut prev = node.prev_named_sibling();
+    while let Some(p) = prev {
+        let gap = &src[p.end_byte()..start];
+        if !(p.kind().contains("comment") || p.kind() == "attribute_item") || !gap.trim().is_empty() || gap.matches('\n').count() > 1 {
+            break
+        }
+        start = p.start_byte();
+        prev = p.prev_named_sibling();
+    }
+    start
+}
+
+/// 节点的文本，多行时合并为一行
+fn node_text(node: Node, src: &str) -> String {
+    src[node.byte_range()].split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::count;
+    use crate::tokenizer::test_tokenizer;
+
+    const RUST: &str = "use std::fmt;\n\n/// A point\n#[derive(Debug)]\nstruct Point {\n    x: i32,\n}\n\nimpl Point {\n    fn new() -> Self {\n        Point { x: 0 }\n    }\n}\n\nfn main() {\n    println!(\"hi\");\n}\n";
+    const PYTHON: &str = "import os\n\nclass A:\n    def f(self):\n        return 1\n\n    def g(self):\n        pass\n\ndef main():\n    print(os.name)\n";
+
+    /// 节点label中的token数
+    fn tokens(tree: &Tree<String>) -> usize {
+        tree.root.rsplit_once(" (").unwrap().1.trim_end_matches(" tokens)").parse().unwrap()
+    }
+
+    /// 顶层节点之和等于文件的token数，方法之和不超过所在的类或impl
+    fn check_sums(file: &str, text: &str) -> Vec<Tree<String>> {
+        let leaves = symbol_tree(test_tokenizer(), Path::new(file), text, SpecialTokens::Allow);
+        assert_eq!(leaves.iter().map(tokens).sum::<usize>(), count(test_tokenizer(), text, SpecialTokens::Allow));
+        for leaf in &leaves {
+            assert!(leaf.leaves.iter().map(tokens).sum::<usize>() <= tokens(leaf));
+        }
+        leaves
+    }
+
+    #[test]
+    fn rust() {
+        let leaves = check_sums("a.rs", RUST);
+        let labels: Vec<&str> = leaves.iter().map(|l| l.root.as_str()).collect();
+        assert_eq!(labels, ["struct Point (17 tokens)", "impl Point (21 tokens)", "fn main (10 tokens)", "other code (5 tokens)"]);
+        assert_eq!(leaves[1].leaves[0].root, "fn new (15 tokens)");
+        // 上方的文档注释和属性计入struct
+        assert!(tokens(&leaves[0]) > count(test_tokenizer(), "struct Point {\n    x: i32,\n}", SpecialTokens::Allow));
+    }
+
+    #[test]
+    fn python() {
+        let leaves = check_sums("a.py", PYTHON);
+        let labels: Vec<&str> = leaves.iter().map(|l| l.root.as_str()).collect();
+        assert_eq!(labels, ["class A (20 tokens)", "def main (8 tokens)", "other code (4 tokens)"]);
+        assert_eq!(leaves[0].leaves.iter().map(|l| l.root.as_str()).collect::<Vec<&str>>(), ["def f (7 tokens)", "def g (5 tokens)"]);
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(symbol_tree(test_tokenizer(), Path::new("a.txt"), RUST, SpecialTokens::Allow).is_empty());
+        assert!(symbol_tree(test_tokenizer(), Path::new("a.rs"), "// only a comment\n", SpecialTokens::Allow).is_empty());
+    }
+}
@@ -1,47 +1,40 @@
 use std::fs::read;
 use std::path::{Path, PathBuf};
 
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
 use tiktoken_rs::{
     o200k_base, // GPT-4o models
     cl100k_base, // ChatGPT models text-embedding-ada-002
     p50k_base, // Code models, text-davinci-002, text-davinci-003
     p50k_edit, // edit models like text-davinci-edit-001, code-davinci-edit-001
     r50k_base, // GPT-3 models like davinci, also known as gpt2
-    CoreBPE,
 };
 
 use crate::{
+    binary::BinaryClassifier,
+    counter::{FileCount, Options, file_count},
     error::MyError,
-    traverse::traverse_directory,
+    image::image_size,
+    normalize::{Normalize, tokens_str},
+    tokenizer::{Rank, Tiktoken, Tokenizer},
+    tools::tools_token,
+    traverse::walk_directory,
 };
 
-/// 根据指定编码类型，返回CoreBPE对象
-fn get_tokenizer(encoding: &str) -> Result<CoreBPE, MyError> {
-    match encoding {
-        "o200k_base" => match o200k_base() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "o200k_base".to_string(), error: e}),
-        },
-        "cl100k_base" => match cl100k_base() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "cl100k_base".to_string(), error: e}),
-        },
-        "p50k_base" => match p50k_base() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "cl100k_base".to_string(), error: e}),
-        },
-        "p50k_edit" => match p50k_edit() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "cl100k_base".to_string(), error: e}),
-        },
-        "r50k_base" | "gpt2" => match r50k_base() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "cl100k_base".to_string(), error: e}),
-        },
-        _ => match o200k_base() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(MyError::TokenizerError{tokenizer: "o200k_base".to_string(), error: e}),
-        },
+/// 根据指定编码类型，返回tiktoken分词器，不支持的编码使用o200k_base
+/// 同时指定每种编码普通token的数量，用于检查token ID是否在词表中
+pub fn get_tokenizer(encoding: &str) -> Result<Tiktoken, MyError> {
+    let (name, bpe, vocab) = match encoding {
+        "cl100k_base" => ("cl100k_base", cl100k_base(), 100256),
+        "p50k_base" => ("p50k_base", p50k_base(), 50281),
+        "p50k_edit" => ("p50k_edit", p50k_edit(), 50281),
+        "r50k_base" | "gpt2" => ("r50k_base", r50k_base(), 50256),
+        _ => ("o200k_base", o200k_base(), 199998),
+    };
+    match bpe {
+        Ok(b) => Ok(Tiktoken::new(name, b, vocab)),
+        Err(e) => Err(MyError::TokenizerError{tokenizer: name.to_string(), error: e}),
     }
 }
 
@@ -51,58 +44,56 @@ pub fn calculate_token(
     string: Option<String>,
     path: Option<PathBuf>,
     encoding: &str,
-    max_size: u64,
-    max_size_str: &str,
-    min_token: usize,
-    max_token: usize,
-    only_valid: bool,
+    options: &Options,
+    strict: bool,
+    tools: Option<PathBuf>,
 ) -> Result<(), MyError> {
+    let special = options.special;
     let bpe = get_tokenizer(encoding)?;
-    let mut num: usize;
+    let num: usize;
+    let mut unexamined = 0; // 无法检查的路径数，--strict时大于0则以错误退出
     // 指定的文件
     if let Some(files) = files {
-        let mut file_size: u64;
         for f in files {
-            file_size = f.metadata().unwrap().len();
-            if file_size <= max_size {
-                let mut file_token = FileToken::new(&f, file_size);
-                if file_token.not_binary() {
-                    if file_token.string.is_empty() {
-                        if only_valid {
-                            continue
-                        }
-                        println!("{} ({}, 0 token)", f.display(), file_token.size);
-                    } else {
-                        // 代码中含有无效UTF-8字符则报错，REPLACEMENT_CHARACTER表示无效字符“�”
-                        if file_token.string.contains(char::REPLACEMENT_CHARACTER) {
-                            if only_valid {
-                                continue
-                            }
-                            println!("{} ({}, contain invalid UTF-8)", f.display(), file_token.size);
-                        } else {
-                            num = bpe.encode_with_special_tokens(&file_token.string).len();
-                            if num >= min_token && num <= max_token {
-                                println!("{} ({}, {} tokens)", f.display(), file_token.size, num);
-                            }
-                        }
-                    }
-                } else {
-                    println!("{} ({}, binary file)", f.display(), file_token.size);
-                }
-            } else {
-                println!("{} ({}, file size {} bytes > {})", f.display(), get_file_size(file_size), file_size, max_size_str);
+            let file = match file_count(&bpe, &f, options) {
+                Ok(file) => file,
+                Err(e) if !options.fail_fast => { // 无法读取的文件显示错误状态，继续计算其他文件
+                    unexamined += 1;
+                    FileCount::failed(&f, &e)
+                },
+                Err(e) => return Err(e),
+            };
+            if file.kept(options) { // 不在token数范围内的文件、only_valid时没有计算token的文件不输出
+                println!("{}", file.tree(&f.display().to_string()).to_string().trim_end());
             }
         }
     }
     // 指定的字符串
     if let Some(s) = string {
-        num = bpe.encode_with_special_tokens(&s).len();
-        println!("-s string: {} tokens", num);
+        let found = find_special_tokens(&bpe, &s);
+        if special == SpecialTokens::Disallow && !found.is_empty() {
+            println!("-s string: contain special tokens: {}", special_tokens_str(&found));
+        } else if options.normalize.is_empty() {
+            num = count(&bpe, &s, special);
+            println!("-s string: {} tokens", num);
+        } else {
+            let raw = count(&bpe, &s, special);
+            num = count(&bpe, &options.normalize.apply(&s), special);
+            println!("-s string: {}", tokens_str(Some(raw), num));
+        }
+    }
+    // 指定的tool/function定义
+    if let Some(t) = tools {
+        println!("{}", tools_token(&t, &bpe, special)?);
     }
     // 指定的路径
     if let Some(p) = path {
-        let tree = traverse_directory(&p, bpe, max_size, max_size_str, min_token, max_token, only_valid)?;
-        println!("{}", tree);
+        let dir = walk_directory(&p, &bpe, options)?;
+        println!("{}", dir.tree);
+        unexamined += dir.errors.len();
+    }
+    if strict && unexamined > 0 {
+        return Err(MyError::StrictError{count: unexamined})
     }
     Ok(())
 }
@@ -112,37 +103,249 @@ pub struct FileToken {
     raw: Vec<u8>, // 文件原始内容
     pub size: String, // 转为合适单位的文件大小
     pub string: String, // 转为的字符串
+    pub encoding: Option<&'static Encoding>, // 检测到或指定的非UTF-8编码，UTF-8时为None
+    forced: Option<&'static Encoding>, // --input-encoding指定的编码
+    classifier: BinaryClassifier, // 二进制文件分类器
+    pub binary: Option<String>, // 是二进制文件的原因
 }
 
 impl FileToken {
-    /// 读取文件，创建对象
-    pub fn new(f: &Path, size: u64) -> Self {
+    /// 读取文件，创建对象，读取失败（例如没有权限）返回错误
+    pub fn new(f: &Path, size: u64) -> Result<Self, MyError> {
+        Ok(FileToken{
+            raw: read(f).map_err(|e| MyError::ReadFileError{file: f.display().to_string(), error: e})?,
+            size: get_file_size(size),
+            string: "".to_string(),
+            encoding: None,
+            forced: None,
+            classifier: BinaryClassifier::default(),
+            binary: None,
+        })
+    }
+
+    /// 从内存中的内容创建对象，例如压缩包中的文件
+    pub fn from_bytes(raw: Vec<u8>, size: u64) -> Self {
         FileToken{
-            raw: read(f).unwrap(),
+            raw,
             size: get_file_size(size),
             string: "".to_string(),
+            encoding: None,
+            forced: None,
+            classifier: BinaryClassifier::default(),
+            binary: None,
         }
     }
 
-    /// 检查前50个byte判断是否是二进制文件，如果不是二进制文件则将raw转为String
-    /// 读取文件开头指定数量byte，判断是否`<=0x08`（在ASCII码中该字符及其前面的字符不会出现在文本文件中），满足则说明该文件是二进制文件
-    /// https://github.com/dalance/amber/blob/master/src/pipeline_matcher.rs
+    /// 文件原始内容
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// 指定文件编码，不再自动检测
+    pub fn with_encoding(mut self, forced: Option<&'static Encoding>) -> Self {
+        self.forced = forced;
+        self
+    }
+
+    /// 指定二进制文件分类器
+    pub fn with_binary(mut self, classifier: BinaryClassifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// 从文件头读取图片宽和高，仅支持PNG、JPEG、WebP、GIF，不是图片则返回None
+    pub fn image_size(&self) -> Option<(usize, usize)> {
+        image_size(&self.raw)
+    }
+
+    /// 用分类器判断是否是二进制文件（原因存入binary），如果不是二进制文件则将raw转为String
+    /// UTF-16文件含有大量NUL，有BOM或符合UTF-16特征时不做此检查；不是有效的UTF-8时检测编码（例如GBK、Shift_JIS、windows-1252）并转为UTF-8
     pub fn not_binary(&mut self) -> bool {
-        let mut not_binary = true;
-        for byte in self.raw.iter().take(50) { // 判断前50个byte
-            if byte <= &0x08 {
-                //println!("{:?}, {:?}", &0x08, byte);
-                not_binary = false;
-                break;
-            }
+        let utf16 = match self.forced {
+            Some(e) => (e == UTF_16LE || e == UTF_16BE).then_some(e),
+            None => utf16_encoding(&self.raw),
+        };
+        if utf16.is_none() && let Some(reason) = self.classifier.classify(&self.raw) {
+            self.binary = Some(reason);
+            return false
         }
-        if not_binary {
+        let encoding = match self.forced.or(utf16) {
+            Some(e) => e,
+            None => detect_encoding(&self.raw),
+        };
+        if encoding == UTF_8 {
             self.string = String::from_utf8_lossy(&self.raw).to_string(); // 转为UTF-8
+        } else {
+            self.string = encoding.decode_with_bom_removal(&self.raw).0.into_owned(); // 按检测到的编码转为UTF-8，无效的字符转为“�”
+            self.encoding = Some(encoding);
+        }
+        true
+    }
+
+    /// 文件大小，不是UTF-8编码时加上编码名称，例如`1.20Kb, GBK`
+    pub fn size_encoding(&self) -> String {
+        match self.encoding {
+            Some(e) => format!("{}, {}", self.size, e.name()),
+            None => self.size.clone(),
+        }
+    }
+
+    /// 文件编码名称，UTF-8时为`UTF-8`
+    pub fn encoding_name(&self) -> &'static str {
+        self.encoding.unwrap_or(UTF_8).name()
+    }
+
+    /// 按--normalize规范化转换后的字符串，返回规范化前的token数，没有指定规范化处理则返回None
+    pub fn normalize(&mut self, bpe: &dyn Tokenizer, normalize: &Normalize, special: SpecialTokens) -> Option<usize> {
+        if normalize.is_empty() {
+            return None
+        }
+        let raw = count(bpe, &self.string, special);
+        self.string = normalize.apply(&self.string);
+        Some(raw)
+    }
+
+    /// 不是二进制文件、不是空文件、不含无效UTF-8字符时，返回转换后的字符串
+    pub fn into_text(self) -> Option<String> {
+        self.text_or_reason().ok()
+    }
+
+    /// 同`into_text`，不是有效文本时返回原因，例如`binary file: ...`、`empty`、`contain invalid GBK`
+    pub fn text_or_reason(mut self) -> Result<String, String> {
+        if !self.not_binary() {
+            Err(format!("binary file: {}", self.binary.unwrap_or_default()))
+        } else if self.string.is_empty() {
+            Err("empty".to_string())
+        } else if self.string.contains(char::REPLACEMENT_CHARACTER) {
+            Err(format!("contain invalid {}", self.encoding_name()))
+        } else {
+            Ok(self.string)
         }
-        not_binary
     }
 }
 
+/// 读取文本文件，按--input-encoding指定或检测到的编码（例如UTF-16、GBK、windows-1252）转为UTF-8
+/// 读取失败、二进制文件、含有无效字符时返回错误
+pub fn read_text(path: &Path, options: &Options) -> Result<String, MyError> {
+    let mut file_token = FileToken::new(path, file_len(path)?)?.with_encoding(options.charset).with_binary(options.binary);
+    if !file_token.not_binary() {
+        return Err(MyError::NotTextError{file: path.display().to_string(), reason: format!("binary file: {}", file_token.binary.unwrap_or_default())})
+    }
+    if file_token.string.contains(char::REPLACEMENT_CHARACTER) {
+        return Err(MyError::NotTextError{file: path.display().to_string(), reason: format!("contain invalid {}", file_token.encoding_name())})
+    }
+    Ok(file_token.string)
+}
+
+/// 根据BOM或NUL的分布判断是否是UTF-16，不是则返回None
+/// 没有BOM时，前512个byte中奇数（或偶数）位置的byte一半以上是NUL，且另一位置没有NUL，则认为是UTF-16LE（或UTF-16BE）
+fn utf16_encoding(raw: &[u8]) -> Option<&'static Encoding> {
+    if let Some((e, _)) = Encoding::for_bom(raw) {
+        return (e != UTF_8).then_some(e)
+    }
+    let sample = &raw[..raw.len().min(512) & !1];
+    if sample.len() < 4 {
+        return None
+    }
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if even == 0 && odd * 2 >= pairs {
+        Some(UTF_16LE)
+    } else if odd == 0 && even * 2 >= pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// 检测文本编码，有效的UTF-8，或有效的多字节UTF-8字符多于无效byte序列（个别字符损坏的UTF-8文件）时返回UTF-8，否则用chardetng猜测
+fn detect_encoding(raw: &[u8]) -> &'static Encoding {
+    let (mut valid, mut invalid) = (0, 0); // 有效的非ASCII字符数，无效的byte序列数
+    for chunk in raw.utf8_chunks() {
+        valid += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        invalid += usize::from(!chunk.invalid().is_empty());
+    }
+    if invalid == 0 || valid > invalid {
+        return UTF_8
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(raw, true);
+    detector.guess(None, false)
+}
+
+/// 特殊token（例如`<|endoftext|>`）的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecialTokens {
+    Allow,    // 编码为单个特殊token
+    Disallow, // 含有特殊token的文件和字符串会被标记并列出位置，不计算token数，其他情况按普通文本编码
+    Text,     // 按普通文本编码
+}
+
+/// 按照特殊token处理方式编码字符串
+pub fn encode(bpe: &dyn Tokenizer, text: &str, special: SpecialTokens) -> Vec<Rank> {
+    bpe.encode(text, special == SpecialTokens::Allow)
+}
+
+/// 按照特殊token处理方式计算字符串的token数
+pub fn count(bpe: &dyn Tokenizer, text: &str, special: SpecialTokens) -> usize {
+    bpe.count(text, special == SpecialTokens::Allow)
+}
+
+/// 查找字符串中所有特殊token，按出现顺序返回(特殊token, 行号, 列号)，行号和列号从1开始
+pub fn find_special_tokens(bpe: &dyn Tokenizer, text: &str) -> Vec<(String, usize, usize)> {
+    let specials = bpe.special_tokens();
+    let mut found: Vec<(usize, &str)> = vec![];
+    for special in &specials {
+        found.extend(text.match_indices(special.as_str()).map(|(pos, _)| (pos, special.as_str())));
+    }
+    found.sort();
+    found.into_iter().map(|(pos, special)| {
+        let before = &text[..pos];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (special.to_string(), line, col)
+    }).collect()
+}
+
+/// 特殊token位置的描述，例如`<|endoftext|> at 3:15, <|fim_prefix|> at 10:1`，最多列出3个
+pub fn special_tokens_str(found: &[(String, usize, usize)]) -> String {
+    let mut out = found.iter().take(3).map(|(special, line, col)| format!("{} at {}:{}", special, line, col)).collect::<Vec<String>>().join(", ");
+    if found.len() > 3 {
+        out += &format!(" and {} more", found.len() - 3);
+    }
+    out
+}
+
+/// 计算每个token在字符串中的起始byte位置，最后一项是字符串的总byte数
+/// 注意一个多字节字符可能被拆分到多个token中，此时相应位置不在字符边界上
+/// 依赖Tokenizer::token_bytes对encode返回的每个token都返回Some，返回None的token长度按0计算
+pub fn token_offsets(bpe: &dyn Tokenizer, text: &str, special: SpecialTokens) -> Vec<usize> {
+    let mut offsets = vec![0];
+    let mut pos = 0;
+    for token in encode(bpe, text, special) {
+        pos += bpe.token_bytes(token).map(|b| b.len()).unwrap_or_default();
+        offsets.push(pos);
+    }
+    offsets
+}
+
+/// 文件大小上限的描述，例如`500Kb`、`10Mb`，不限制时是u64::MAX对应的Gb数
+pub fn size_limit_str(max_size: u64) -> String {
+    match max_size {
+        u64::MAX => format!("{}Gb", u64::MAX/1024/1024/1024),
+        s if s % 1073741824 == 0 => format!("{}Gb", s/1073741824),
+        s if s % 1048576 == 0 => format!("{}Mb", s/1048576),
+        s if s % 1024 == 0 => format!("{}Kb", s/1024),
+        s => format!("{} bytes", s),
+    }
+}
+
+/// 获取文件大小（byte），获取失败（例如没有权限、断开的符号链接）返回错误
+pub fn file_len(path: &Path) -> Result<u64, MyError> {
+    path.metadata().map(|m| m.len()).map_err(|e| MyError::MetadataError{file: path.display().to_string(), error: e})
+}
+
 /// 获取文件大小字符串，转为合适的单位
 pub fn get_file_size(size: u64) -> String {
     if size > 1073741824 { // 1Gb = 1024*1024*1024 = 1073741824
@@ -162,3 +365,93 @@ fn size_convert(size: u64, div: u64) -> f64 {
     let right = (size - div * left) as f64 / div as f64; // 小数部分
     left as f64 + right
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, SHIFT_JIS, WINDOWS_1252};
+    use crate::counter::{Counter, Options, file_count};
+    use crate::tokenizer::test_tokenizer;
+
+    #[test]
+    fn utf16_by_bom() {
+        assert_eq!(utf16_encoding(b"\xff\xfea\x00"), Some(UTF_16LE));
+        assert_eq!(utf16_encoding(b"\xfe\xff\x00a"), Some(UTF_16BE));
+        assert_eq!(utf16_encoding(b"\xef\xbb\xbfabcd"), None); // UTF-8 BOM
+    }
+
+    #[test]
+    fn utf16_without_bom() {
+        let le: Vec<u8> = "hello 世界".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let be: Vec<u8> = "hello 世界".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        assert_eq!(utf16_encoding(&le), Some(UTF_16LE));
+        assert_eq!(utf16_encoding(&be), Some(UTF_16BE));
+        assert_eq!(utf16_encoding(b"plain ascii text"), None);
+        assert_eq!(utf16_encoding(b"a\x00"), None); // 太短
+        assert_eq!(utf16_encoding(b"\x00\x00\x00\x00\x01\x02\x03\x04"), None); // 两个位置都有NUL
+    }
+
+    #[test]
+    fn detect_utf8() {
+        assert_eq!(detect_encoding(b"ascii"), UTF_8);
+        assert_eq!(detect_encoding("中文内容，测试。".as_bytes()), UTF_8);
+        let mut damaged = "中文内容，测试。".as_bytes().to_vec();
+        damaged.push(0xff); // 个别损坏的byte
+        assert_eq!(detect_encoding(&damaged), UTF_8);
+    }
+
+    #[test]
+    fn detect_legacy() {
+        assert_eq!(detect_encoding(&GBK.encode("这是一段用于检测编码的中文文本，包含常见的汉字。").0), GBK);
+        assert_eq!(detect_encoding(&SHIFT_JIS.encode("これは文字コードを判定するための日本語の文章です。").0), SHIFT_JIS);
+        assert_eq!(detect_encoding(&WINDOWS_1252.encode("Le café est très bon à Genève, déjà vu.").0), WINDOWS_1252);
+    }
+
+    #[test]
+    fn special_positions() {
+        let text = "a <|endoftext|>\n中文<|endofprompt|>b<|endoftext|>";
+        assert_eq!(find_special_tokens(test_tokenizer(), text), vec![
+            ("<|endoftext|>".to_string(), 1, 3),
+            ("<|endofprompt|>".to_string(), 2, 3), // 列号按字符计算
+            ("<|endoftext|>".to_string(), 2, 19),
+        ]);
+        assert!(find_special_tokens(test_tokenizer(), "<|endoftext| > <endoftext>").is_empty());
+        let found = find_special_tokens(test_tokenizer(), &"<|endoftext|>\n".repeat(5));
+        assert_eq!(special_tokens_str(&found), "<|endoftext|> at 1:1, <|endoftext|> at 2:1, <|endoftext|> at 3:1 and 2 more");
+    }
+
+    #[test]
+    fn special_modes() {
+        let text = "hi<|endoftext|>";
+        assert_eq!(count(test_tokenizer(), text, SpecialTokens::Allow), 2);
+        assert_eq!(encode(test_tokenizer(), text, SpecialTokens::Allow)[1], 199999);
+        // Text和Disallow都按普通文本编码，Disallow在调用者中标记含有特殊token的输入
+        assert!(count(test_tokenizer(), text, SpecialTokens::Text) > 2);
+        assert_eq!(count(test_tokenizer(), text, SpecialTokens::Disallow), count(test_tokenizer(), text, SpecialTokens::Text));
+        assert!(!encode(test_tokenizer(), text, SpecialTokens::Text).contains(&199999));
+    }
+
+    #[test]
+    fn disallow_report() {
+        let dir = std::env::temp_dir().join(format!("tokenum-token-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "line one\nsay <|endoftext|> here\n").unwrap();
+        let mut options = Options{special: SpecialTokens::Disallow, ..Options::default()};
+        let out = file_count(test_tokenizer(), &file, &options).unwrap();
+        assert!(!out.valid);
+        assert_eq!(out.tokens, 0);
+        assert!(out.label.ends_with(", contain special tokens: <|endoftext|> at 2:5"), "{}", out.label);
+        for special in [SpecialTokens::Allow, SpecialTokens::Text] {
+            options.special = special;
+            let out = file_count(test_tokenizer(), &file, &options).unwrap();
+            assert!(out.valid);
+            assert_eq!(out.tokens, count(test_tokenizer(), "line one\nsay <|endoftext|> here\n", special));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        // 字符串
+        let counter = Counter::new(get_tokenizer("o200k_base").unwrap(), Options{special: SpecialTokens::Disallow, ..Options::default()});
+        assert!(matches!(counter.count_str("x<|endoftext|>"), Err(MyError::SpecialTokensError{found, ..}) if found == "<|endoftext|> at 1:2"));
+        assert_eq!(counter.count_str("x").unwrap(), 1);
+    }
+}
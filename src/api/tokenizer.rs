@@ -0,0 +1,168 @@
+use tiktoken_rs::CoreBPE;
+
+/// token ID，与tiktoken_rs::Rank相同，使用其他分词器时不需要依赖tiktoken_rs
+pub type Rank = u32;
+
+/// 分词器，文件、字符串、tree相关的函数都接受任意实现，可以注入mock或特定模型的分词器
+pub trait Tokenizer {
+    /// 分词器名称，例如`o200k_base`
+    fn name(&self) -> &str;
+
+    /// 编码字符串，special为true时文本中的特殊token编码为单个token，否则按普通文本编码
+    fn encode(&self, text: &str, special: bool) -> Vec<Rank>;
+
+    /// 计算字符串的token数，默认为encode结果的长度，只需要计数的后端可以实现更快的方式
+    fn count(&self, text: &str, special: bool) -> usize {
+        self.encode(text, special).len()
+    }
+
+    /// 单个token对应的byte，不在词表中返回None，注意一个token可能只包含多字节字符的一部分
+    /// encode返回的每个token都必须返回Some，且按顺序拼接后等于原字符串，token_offsets据此计算每个token的位置（lines、markdown、chunk等）
+    fn token_bytes(&self, token: Rank) -> Option<Vec<u8>>;
+
+    /// 将token解码为字符串，含有不在词表中的token或结果不是有效的UTF-8时返回错误信息
+    fn decode(&self, tokens: &[Rank]) -> Result<String, String> {
+        let mut bytes: Vec<u8> = vec![];
+        for token in tokens {
+            bytes.extend(self.token_bytes(*token).ok_or(format!("token {} is not in {}", token, self.name()))?);
+        }
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    }
+
+    /// 分词器的特殊token，例如`<|endoftext|>`，默认没有
+    fn special_tokens(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// tiktoken的BPE编码，例如o200k_base、cl100k_base
+pub struct Tiktoken {
+    name:     String,    // 编码名称
+    bpe:      CoreBPE,   // tiktoken的编码器
+    vocab:    Rank,      // 普通token的数量，tiktoken词表中普通token的ID是从0开始连续的
+    specials: Vec<Rank>, // 特殊token的ID
+}
+
+impl Tiktoken {
+    /// vocab是编码中普通token的数量，例如o200k_base为199998，特殊token从编码器中获取
+    pub fn new(name: &str, bpe: CoreBPE, vocab: Rank) -> Self {
+        let specials = bpe.special_tokens().into_iter().flat_map(|s| bpe.encode_with_special_tokens(s)).collect();
+        Tiktoken{name: name.to_string(), bpe, vocab, specials}
+    }
+
+    /// token ID是否在词表中
+    pub fn contains(&self, token: Rank) -> bool {
+        token < self.vocab || self.specials.contains(&token)
+    }
+}
+
+impl Tokenizer for Tiktoken {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn encode(&self, text: &str, special: bool) -> Vec<Rank> {
+        if special {
+            self.bpe.encode_with_special_tokens(text)
+        } else {
+            self.bpe.encode_ordinary(text)
+        }
+    }
+
+    /// 先检查是否在词表中，再获取原始byte，只包含多字节字符一部分的token也能获取
+    fn token_bytes(&self, token: Rank) -> Option<Vec<u8>> {
+        if !self.contains(token) {
+            return None
+        }
+        self.bpe._decode_native_and_split(vec![token]).next()
+    }
+
+    fn decode(&self, tokens: &[Rank]) -> Result<String, String> {
+        self.bpe.decode(tokens.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn special_tokens(&self) -> Vec<String> {
+        self.bpe.special_tokens().into_iter().map(|s| s.to_string()).collect()
+    }
+}
+
+/// 测试共用的o200k_base分词器，只加载一次
+#[cfg(test)]
+pub(crate) fn test_tokenizer() -> &'static Tiktoken {
+    static TOKENIZER: std::sync::OnceLock<Tiktoken> = std::sync::OnceLock::new();
+    TOKENIZER.get_or_init(|| crate::token::get_tokenizer("o200k_base").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::get_tokenizer;
+
+    #[test]
+    fn vocabulary() {
+        for encoding in ["r50k_base", "p50k_base", "p50k_edit", "cl100k_base", "o200k_base"] {
+            let bpe = get_tokenizer(encoding).unwrap();
+            assert!((0..bpe.vocab).all(|t| bpe.token_bytes(t).is_some_and(|b| !b.is_empty())), "{}", encoding);
+            for s in bpe.special_tokens() {
+                let id = bpe.encode(&s, true)[0];
+                assert_eq!(bpe.token_bytes(id), Some(s.into_bytes()));
+            }
+            let unknown = (bpe.vocab..bpe.vocab + 100).find(|t| !bpe.specials.contains(t)).unwrap();
+            assert_eq!(bpe.token_bytes(unknown), None, "{}", encoding);
+            assert_eq!(bpe.token_bytes(Rank::MAX), None);
+        }
+    }
+
+    #[test]
+    fn partial_utf8_token() {
+        let bpe = test_tokenizer();
+        let tokens = bpe.encode("𝔘", false);
+        assert!(tokens.len() > 1);
+        let bytes: Vec<u8> = tokens.iter().flat_map(|t| bpe.token_bytes(*t).unwrap()).collect();
+        assert_eq!(bytes, "𝔘".as_bytes());
+        assert!(tokens.iter().any(|t| std::str::from_utf8(&bpe.token_bytes(*t).unwrap()).is_err())); // 单个token不是有效的UTF-8
+    }
+
+    #[test]
+    fn decode_unknown() {
+        let bpe = test_tokenizer();
+        assert_eq!(bpe.decode(&bpe.encode("hello", false)).unwrap(), "hello");
+        assert!(Tokenizer::decode(bpe, &[Rank::MAX]).is_err());
+    }
+
+    /// 同README中的示例，每个词连同其后的1个空白字符是1个token
+    #[derive(Default)]
+    struct Words {
+        vocab: std::sync::Mutex<Vec<String>>, // token ID对应的文本
+    }
+
+    impl Tokenizer for Words {
+        fn name(&self) -> &str {
+            "words"
+        }
+
+        fn encode(&self, text: &str, _: bool) -> Vec<Rank> {
+            let mut vocab = self.vocab.lock().unwrap();
+            text.split_inclusive(char::is_whitespace).map(|w| match vocab.iter().position(|v| v == w) {
+                Some(i) => i as Rank,
+                None => {
+                    vocab.push(w.to_string());
+                    (vocab.len() - 1) as Rank
+                },
+            }).collect()
+        }
+
+        fn token_bytes(&self, token: Rank) -> Option<Vec<u8>> {
+            self.vocab.lock().unwrap().get(token as usize).map(|w| w.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn custom_offsets() {
+        let words = Words::default();
+        let text = "one two\nthree  four\n";
+        assert_eq!(crate::token::token_offsets(&words, text, crate::token::SpecialTokens::Allow), [0, 4, 8, 14, 15, 20]);
+        assert_eq!(crate::lines::line_tokens(&words, text, crate::token::SpecialTokens::Allow), [2, 3]);
+        assert_eq!(words.decode(&words.encode(text, false)).unwrap(), text);
+    }
+}
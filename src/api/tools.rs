@@ -0,0 +1,203 @@
+use std::fs::read_to_string;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+use termtree::Tree;
+
+use crate::{
+    error::MyError,
+    token::{
+        SpecialTokens,
+        count,
+        encode,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// tool定义渲染到prompt后，除了函数定义本身之外的固定开销token数
+/// 参考：https://github.com/hmarr/openai-chat-tokens
+const TOOLS_OVERHEAD: usize = 9;
+
+/// 计算JSON文件中tool/function定义加入prompt后的token数，返回每个tool及总token数的tree字符串
+/// 支持的格式：`[{"type": "function", "function": {...}}]`、`[{"name": ..., "parameters": {...}}]`、`{"tools": [...]}`、`{"functions": [...]}`、单个tool对象
+/// 参数schema也可以放在`input_schema`中
+/// 按照模型实际看到的TypeScript风格`namespace functions {...}`序列化后再计算token数
+pub fn tools_token(file: &Path, bpe: &dyn Tokenizer, special: SpecialTokens) -> Result<String, MyError> {
+    let content = read_to_string(file).map_err(|e| MyError::ReadFileToStringError{file: file.display().to_string(), error: e})?;
+    let json: Value = serde_json::from_str(&content).map_err(|e| MyError::ParseJsonError{file: file.display().to_string(), error: e})?;
+    // 获取所有tool定义
+    let tools: Vec<&Value> = match &json {
+        Value::Array(a) => a.iter().collect(),
+        Value::Object(o) => match o.get("tools").or(o.get("functions")) {
+            Some(Value::Array(a)) => a.iter().collect(),
+            _ => vec![&json],
+        },
+        _ => return Err(MyError::ParaError{para: format!("--tools {} must be a JSON array or object of tool definitions", file.display())}),
+    };
+    // 序列化每个tool并计算token数
+    let mut rendered: Vec<String> = vec![];
+    let mut leaves: Vec<Tree<String>> = vec![];
+    let mut sum = 0; // 每个tool单独计算的token数之和
+    for (i, tool) in tools.into_iter().enumerate() {
+        let function = match tool.get("function") { // `{"type": "function", "function": {...}}`格式
+            Some(f) => f,
+            None => tool,
+        };
+        let name = match function.get("name").and_then(Value::as_str) {
+            Some(n) => n,
+            None => return Err(MyError::ParaError{para: format!("--tools {}: tool #{} has no name", file.display(), i+1)}),
+        };
+        let tool_str = format_function(name, function);
+        let num = count(bpe, &tool_str, special);
+        sum += num;
+        leaves.push(Tree::new(format!("{} ({} tokens)", name, num)));
+        rendered.push(tool_str);
+    }
+    // 整体序列化后计算总token数，与单独计算之和的差值作为namespace开销
+    let total = if rendered.is_empty() {
+        0
+    } else {
+        encode(bpe, &format!("namespace functions {{\n\n{}}} // namespace functions", rendered.concat()), special).len() + TOOLS_OVERHEAD
+    };
+    if total > 0 {
+        leaves.push(Tree::new(format!("namespace overhead ({} tokens)", total.saturating_sub(sum))));
+    }
+    let root = format!("{} ({} tools, total {} tokens)", file.display(), rendered.len(), total);
+    Ok(Tree::new(root).with_leaves(leaves).to_string().trim_end().to_string())
+}
+
+/// 将单个function定义序列化为`type name = (_: {...}) => any;`形式
+fn format_function(name: &str, function: &Value) -> String {
+    let mut lines: Vec<String> = vec![];
+    if let Some(d) = function.get("description").and_then(Value::as_str) {
+        lines.push(format!("// {}", d));
+    }
+    let parameters = function.get("parameters").or(function.get("input_schema"));
+    match parameters.and_then(|p| p.get("properties")).and_then(Value::as_object) {
+        Some(properties) if !properties.is_empty() => {
+            lines.push(format!("type {} = (_: {{", name));
+            lines.push(format_properties(properties, parameters.unwrap(), 0));
+            lines.push("}) => any;".to_string());
+        },
+        _ => lines.push(format!("type {} = () => any;", name)),
+    }
+    lines.push("".to_string());
+    lines.push("".to_string());
+    lines.join("\n")
+}
+
+/// 序列化object的每个属性，`indent`是缩进空格数，只有前两层会保留属性的描述
+fn format_properties(properties: &Map<String, Value>, obj: &Value, indent: usize) -> String {
+    let required: Vec<&str> = match obj.get("required").and_then(Value::as_array) {
+        Some(r) => r.iter().filter_map(Value::as_str).collect(),
+        None => vec![],
+    };
+    let mut lines: Vec<String> = vec![];
+    for (key, param) in properties {
+        if indent < 2 && let Some(d) = param.get("description").and_then(Value::as_str) {
+            lines.push(format!("// {}", d));
+        }
+        if required.contains(&key.as_str()) {
+            lines.push(format!("{}: {},", key, format_type(param, indent)));
+        } else {
+            lines.push(format!("{}?: {},", key, format_type(param, indent)));
+        }
+    }
+    lines.iter().map(|l| " ".repeat(indent) + l).collect::<Vec<String>>().join("\n")
+}
+
+/// 将JSON schema类型序列化为TypeScript类型
+fn format_type(param: &Value, indent: usize) -> String {
+    // enum和const直接列出所有可选值
+    if let Some(e) = param.get("enum").and_then(Value::as_array) {
+        return e.iter().map(format_literal).collect::<Vec<String>>().join(" | ")
+    }
+    if let Some(c) = param.get("const") {
+        return format_literal(c)
+    }
+    // anyOf、oneOf转为联合类型
+    if let Some(u) = param.get("anyOf").or(param.get("oneOf")).and_then(Value::as_array) {
+        return u.iter().map(|p| format_type(p, indent)).collect::<Vec<String>>().join(" | ")
+    }
+    match param.get("type") {
+        Some(Value::String(t)) => format_single_type(t, param, indent),
+        Some(Value::Array(t)) => t.iter().filter_map(Value::as_str).map(|t| format_single_type(t, param, indent)).collect::<Vec<String>>().join(" | "), // 例如`["string", "null"]`
+        _ => "any".to_string(),
+    }
+}
+
+/// 序列化单个JSON schema类型名
+fn format_single_type(t: &str, param: &Value, indent: usize) -> String {
+    match t {
+        "string" => "string".to_string(),
+        "number" | "integer" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "null" => "null".to_string(),
+        "object" => match param.get("properties").and_then(Value::as_object) {
+            Some(p) => format!("{{\n{}\n}}", format_properties(p, param, indent + 2)),
+            None => "object".to_string(),
+        },
+        "array" => match param.get("items") {
+            Some(items) => format!("{}[]", format_type(items, indent)),
+            None => "any[]".to_string(),
+        },
+        _ => "any".to_string(),
+    }
+}
+
+/// enum、const的值，字符串加上双引号
+fn format_literal(v: &Value) -> String {
+    match v {
+        Value::String(s) => format!("\"{}\"", s),
+        _ => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn function_without_parameters() {
+        let f = json!({"name": "now", "description": "Get the current time"});
+        assert_eq!(format_function("now", &f), "// Get the current time\ntype now = () => any;\n\n");
+    }
+
+    #[test]
+    fn function_with_parameters() {
+        let f = json!({
+            "name": "get_weather",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string", "description": "City name"},
+                    "unit": {"type": "string", "enum": ["c", "f"]},
+                    "days": {"type": "integer"},
+                },
+                "required": ["city"],
+            },
+        });
+        assert_eq!(
+            format_function("get_weather", &f),
+            "type get_weather = (_: {\n// City name\ncity: string,\nunit?: \"c\" | \"f\",\ndays?: number,\n}) => any;\n\n",
+        );
+    }
+
+    #[test]
+    fn input_schema_and_nested_types() {
+        let f = json!({
+            "input_schema": {
+                "properties": {
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "note": {"type": ["string", "null"]},
+                    "opts": {"type": "object", "properties": {"deep": {"type": "boolean"}}, "required": ["deep"]},
+                },
+            },
+        });
+        assert_eq!(
+            format_function("search", &f),
+            "type search = (_: {\ntags?: string[],\nnote?: string | null,\nopts?: {\n  deep: boolean,\n},\n}) => any;\n\n",
+        );
+    }
+}
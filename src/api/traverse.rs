@@ -1,45 +1,76 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
 use termtree::Tree;
-use tiktoken_rs::CoreBPE;
 
 use crate::{
+    breakdown::Breakdown,
+    counter::{DirCount, FileCount, Options, file_count, skip_or_fail},
     token::{
         FileToken,
+        file_len,
         get_file_size,
     },
-    error::MyError,
+    error::{MyError, io_status},
+    tokenizer::Tokenizer,
 };
 
-/// 递归获取指定项目路径下所有文件
+/// 递归获取指定项目路径下所有文件，返回tree字符串
 pub fn traverse_directory(
     root_path: &Path,
-    bpe: CoreBPE,
-    max_size: u64,
-    max_size_str: &str,
-    min_token: usize,
-    max_token: usize,
-    only_valid: bool
+    bpe: &dyn Tokenizer,
+    options: &Options,
 ) -> Result<String, MyError> {
+    walk_directory(root_path, bpe, options).map(|d| d.tree)
+}
+
+/// 递归计算指定路径下所有文件的token数，返回总token数、总大小、包含在结果中的文件以及tree字符串
+pub fn walk_directory(
+    root_path: &Path,
+    bpe: &dyn Tokenizer,
+    options: &Options,
+) -> Result<DirCount, MyError> {
     // 初始化
     let canonical_root_path = root_path.canonicalize()?; // 获取绝对路径
-    let parent_prefix = canonical_root_path.parent().unwrap(); // 父路径，作为后面每个路径要去除的前缀
+    let parent_prefix = canonical_root_path.parent().unwrap_or(&canonical_root_path); // 父路径，作为后面每个路径要去除的前缀，指定的路径是`/`时为其本身
     let parent_directory = match &canonical_root_path.file_name() { // 获取指定路径的文件夹名，`file_name`获取指定path的最后一项
         Some(name) => name.to_string_lossy().to_string(), // 返回指定path的最后一项，可能是文件，也可能是文件夹
-        None => canonical_root_path.to_str().unwrap().to_string(), // 指定的path是`/`或以`..`结尾时`file_name`会返回None，此时直接返回指定的path字符串
+        None => canonical_root_path.to_string_lossy().to_string(), // 指定的path是`/`或以`..`结尾时`file_name`会返回None，此时直接返回指定的path字符串
     };
-    let mut file_size: u64 = 0; // 存储文件大小
     let mut idx = 0; // 每个路径的id
-    let mut tokens = 0; // 计算的文件token数
-    let mut dir_tokens: HashMap<usize, (String, usize, u64)> = HashMap::from([(0, (parent_directory.clone(), 0, 0))]); // key: 每个路径的id，value: (该路径去除前缀后的路径字符串, 该路径下所有文件的总token数, 该路径下所有文件的总大小)
+    let root_rltv_path = canonical_root_path.strip_prefix(parent_prefix).unwrap_or(&canonical_root_path).to_path_buf(); // 指定路径去除前缀后的路径，指定的路径是`/`时为空
+    let mut dir_tokens: HashMap<usize, (PathBuf, usize, u64)> = HashMap::from([(0, (root_rltv_path, 0, 0))]); // key: 每个路径的id，value: (该路径去除前缀后的路径，文件名不是UTF-8时也能匹配, 该路径下所有文件的总token数, 该路径下所有文件的总大小)
+    let mut files: Vec<FileCount> = vec![]; // 包含在tree中的文件
+    let mut breakdown_total = Breakdown::default(); // --breakdown时所有源代码文件的代码、注释、字符串、空白token数之和
+    let mut source_files = 0; // --breakdown时源代码文件数
+    let (mut raw_total, mut normalized_total) = (0, 0); // --normalize时所有文本文件规范化前、后的token数之和
+    let mut failed: Option<MyError> = None; // --fail-fast时第一个无法读取的文件或遍历错误
+    let mut errors: Vec<(PathBuf, String)> = vec![]; // 无法检查的路径及原因，显示在tree结尾的warnings中
     // 创建tree
-    let tree = WalkBuilder::new(&canonical_root_path)
-        .git_ignore(true)
+    let tree = walk_builder(&canonical_root_path, options)?
         .build()
-        .filter_map(|e| e.ok())
         .fold(Tree::new(parent_directory.to_owned()+" srx0"), |mut root, entry| { // 遍历指定路径下每一项，以指定路径作为根路径，递归添加子项
+            if failed.is_some() {
+                return root
+            }
+            let entry = match entry {
+                Ok(e) => e,
+                Err(e) => { // 无法读取的文件夹、.gitignore等，记录后继续遍历
+                    let (path, status) = walk_error(&e);
+                    let path = match path {
+                        Some(p) => p.strip_prefix(parent_prefix).map(|r| r.to_path_buf()).unwrap_or(p),
+                        None => dir_tokens[&0].0.clone(), // 没有路径的错误显示为指定的路径
+                    };
+                    if options.fail_fast {
+                        failed = Some(MyError::WalkError{path: path.display().to_string(), error: status});
+                    } else {
+                        errors.push((path, status));
+                    }
+                    return root
+                },
+            };
             let path = entry.path(); // 当前项的路径
             if let Ok(relative_path) = path.strip_prefix(&canonical_root_path) { // 获取相对路径
                 // 递归获取指定路径下所有项，创建树结构，用于显示在生成文件的起始
@@ -62,74 +93,44 @@ pub fn traverse_directory(
                     {
                         &mut current_tree.leaves[pos] // 找到pos索引，则当前树结构更新为以该叶子节点为root的树结构，返回可变引用
                     } else { // 此时说明当前component不在当前树结构中
-                        // 当前项是文件，且不是二进制文件，且大小在10M范围内
-                        let component_str_token = if path.is_file() {
-                            // 判断文件大小
-                            file_size = path.metadata().unwrap().len();
-                            if file_size <= max_size {
-                                let mut file_token = FileToken::new(path, file_size);
-                                if file_token.not_binary() {
-                                    if file_token.string.is_empty() {
-                                        if only_valid {
-                                            continue
-                                        }
-                                        format!("{} ({}, 0 token)", component_str, file_token.size)
-                                    } else {
-                                        // 代码中含有无效UTF-8字符则报错，REPLACEMENT_CHARACTER表示无效字符“�”
-                                        if file_token.string.contains(char::REPLACEMENT_CHARACTER) {
-                                            if only_valid {
-                                                continue
-                                            }
-                                            //println!("[warning]: invalid UTF-8 in {}", relative_path.display());
-                                            format!("{} ({}, contain invalid UTF-8)", component_str, file_token.size)
-                                        } else {
-                                            tokens = bpe.encode_with_special_tokens(&file_token.string).len(); // 计算该文件token数
-                                            if tokens < min_token || tokens > max_token { // 该文件token数超不在指定上下限范围内，则不写入tree中
-                                                continue
-                                            }
-                                            let rltv_path = path.strip_prefix(parent_prefix).unwrap(); // 当前文件路径去除前缀
-                                            for i in 0..=idx { // 遍历已访问的每个路径，如果该路径是当前文件的父级路径，则该路径总大小和总token数要加上当前文件的大小和token数
-                                                if rltv_path.starts_with(&dir_tokens.get(&i).unwrap().0) {
-                                                    dir_tokens.get_mut(&i).unwrap().1 += tokens;
-                                                    dir_tokens.get_mut(&i).unwrap().2 += file_size;
-                                                }
-                                            }
-                                            format!("{} ({}, {} tokens)", component_str, file_token.size, tokens)
-                                        }
-                                    }
-                                } else {
-                                    if only_valid {
-                                        continue
-                                    }
-                                    let rltv_path = path.strip_prefix(parent_prefix).unwrap(); // 当前文件路径去除前缀
-                                    for i in 0..=idx { // 遍历已访问的每个路径，如果该路径是当前文件的父级路径，则该路径总大小要加上当前文件的大小
-                                        if rltv_path.starts_with(&dir_tokens.get(&i).unwrap().0) {
-                                            dir_tokens.get_mut(&i).unwrap().2 += file_size;
-                                        }
-                                    }
-                                    //println!("[skip]: this file might be a binary file: {}", relative_path.display());
-                                    format!("{} ({}, binary file)", component_str, file_token.size)
-                                }
-                            } else {
-                                if only_valid {
+                        let new_tree = if path.is_file() || !path.is_dir() && path.metadata().is_err() { // 文件，以及断开的符号链接、符号链接循环等无法获取信息的项
+                            // 计算文件的token数，不在token数范围内的文件、only_valid时没有计算token的文件不写入tree中
+                            let mut file = match file_count(bpe, path, options) {
+                                Ok(file) => file,
+                                Err(e) if !options.fail_fast => FileCount::failed(path, &e), // 无法读取的文件作为错误节点，继续遍历
+                                Err(e) => {
+                                    failed.get_or_insert(e); // --fail-fast时记录第一个错误，跳过之后的所有项
                                     continue
-                                }
-                                let rltv_path = path.strip_prefix(parent_prefix).unwrap(); // 当前文件路径去除前缀
-                                for i in 0..=idx { // 遍历已访问的每个路径，如果该路径是当前文件的父级路径，则该路径总大小要加上当前文件的大小
-                                    if rltv_path.starts_with(&dir_tokens.get(&i).unwrap().0) {
-                                        dir_tokens.get_mut(&i).unwrap().2 += file_size;
-                                    }
-                                }
-                                //println!("[skip]: file size {} > {}, {}", file_size, max_size, relative_path.display());
-                                format!("{} ({}, file size {} bytes > {})", component_str, get_file_size(file_size), file_size, max_size_str)
+                                },
+                            };
+                            if !file.kept(options) {
+                                continue
                             }
-                        } else {
+                            let rltv_path = path.strip_prefix(parent_prefix).unwrap();
+                            if let Some(status) = &file.error {
+                                errors.push((rltv_path.to_path_buf(), status.clone()));
+                            }
+                            add_to_parents(&mut dir_tokens, idx, rltv_path, file.tokens, file.size);
+                            if let Some(r) = file.raw {
+                                raw_total += r;
+                                normalized_total += file.tokens;
+                            }
+                            if let Some(b) = &file.breakdown {
+                                breakdown_total.add(b);
+                                source_files += 1;
+                            }
+                            let new_tree = file.tree(&component_str); // 文件名后面加上token数或没有计算token的原因，子节点是函数、cell、压缩包成员等
+                            file.path = rltv_path.to_path_buf();
+                            files.push(file);
+                            new_tree
+                        } else if path.is_dir() {
                             idx += 1;
                             let rltv_path = path.strip_prefix(parent_prefix).unwrap();
-                            dir_tokens.insert(idx, (rltv_path.to_str().unwrap().to_string(), 0, 0));
-                            format!("{} srx{}", component_str, idx) // 这里在路径后面加上` srx编号`，例如` srx0`、` srx1`，最后会根据这个idx编号从dir_tokens中获取该路径的总token数
+                            dir_tokens.insert(idx, (rltv_path.to_path_buf(), 0, 0));
+                            Tree::new(format!("{} srx{}", component_str, idx)) // 这里在路径后面加上` srx编号`，例如` srx0`、` srx1`，最后会根据这个idx编号从dir_tokens中获取该路径的总token数
+                        } else { // FIFO、socket、设备等特殊文件，读取可能会阻塞，不写入tree中
+                            continue
                         };
-                        let new_tree = Tree::new(component_str_token); // 以当前component（如果是文件，则后面加上了token数或没有计算token的原因，如果是文件夹则仅有名称）创建新的tree
                         current_tree.leaves.push(new_tree); // 将刚创建的tree作为叶子节点加入到当前树结构中
                         current_tree.leaves.last_mut().unwrap() // 返回当前树结构中新增的节点的可变引用
                     };
@@ -137,6 +138,9 @@ pub fn traverse_directory(
             }
             root
         });
+    if let Some(e) = failed {
+        return Err(e)
+    }
     //println!("{:?}", dir_tokens);
     let mut out: Vec<String> = vec![];
     for i in tree.to_string().split("\n") { // 遍历输出字符串tree的每行，根据其中路径后面的id获取相应总token数
@@ -156,5 +160,156 @@ pub fn traverse_directory(
             out.push(i.to_string());
         }
     }
-    Ok(out.join("\n"))
+    // 规范化前、后整个tree的token数，图片等不受规范化影响
+    if !options.normalize.is_empty() {
+        let total = dir_tokens.get(&0).unwrap().1;
+        let raw = total - normalized_total + raw_total;
+        let end = out.len() - usize::from(out.last().is_some_and(|l| l.is_empty())); // 放在tree结尾的换行之前
+        let saves = if raw >= total {
+            format!("saves {} tokens ({:.1}%)", raw - total, (raw - total) as f64 * 100.0 / raw.max(1) as f64)
+        } else {
+            format!("adds {} tokens", total - raw)
+        };
+        out.insert(end, format!("normalized whole tree: raw {} tokens, normalized {} tokens, {}", raw, total, saves));
+    }
+    // 所有源代码文件的汇总，整个tree去除注释、规范化空白后的token数只计算源代码文件的变化
+    if options.breakdown && source_files > 0 {
+        let total = dir_tokens.get(&0).unwrap().1;
+        let b = breakdown_total;
+        let end = out.len() - usize::from(out.last().is_some_and(|l| l.is_empty())); // 放在tree结尾的换行之前
+        out.insert(end, format!(
+            "breakdown of {} source files ({} tokens): code {}, comment {}, string {}, whitespace {}; whole tree {} tokens, {} without comments, {} with normalized whitespace",
+            source_files, b.total(), b.code, b.comment, b.string, b.whitespace, total, total - b.total() + b.stripped, total - b.total() + b.normalized,
+        ));
+    }
+    // 无法检查的路径，例如没有权限的文件、文件夹，断开的符号链接
+    if !errors.is_empty() {
+        let end = out.len() - usize::from(out.last().is_some_and(|l| l.is_empty())); // 放在tree结尾的换行之前
+        let mut warnings = vec![format!("warnings: {} paths could not be examined", errors.len())];
+        warnings.extend(errors.iter().map(|(p, s)| format!("  {}: {}", p.display(), s)));
+        out.splice(end..end, warnings);
+    }
+    let (tokens, size) = (dir_tokens[&0].1, dir_tokens[&0].2);
+    Ok(DirCount{tokens, size, files, errors, tree: out.join("\n")})
+}
+
+/// 递归获取指定路径下所有有效文本文件（不是二进制文件、大小不超过-m、不是空文件、不含无效UTF-8字符），按文件名排序
+/// 返回(以指定路径的文件夹名开头的相对路径, 文件内容)，无法读取的路径及错误状态加入skipped，fail_fast时返回错误
+pub fn text_files(root_path: &Path, options: &Options, skipped: &mut Vec<(PathBuf, String)>) -> Result<Vec<(PathBuf, String)>, MyError> {
+    let canonical_root_path = root_path.canonicalize()?; // 获取绝对路径
+    let parent_prefix = canonical_root_path.parent().unwrap_or(&canonical_root_path); // 父路径，作为后面每个路径要去除的前缀
+    let mut files: Vec<(PathBuf, String)> = vec![];
+    for entry in walk_builder(&canonical_root_path, options)?.sort_by_file_name(|a, b| a.cmp(b)).build() {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => { // 无法读取的文件夹等
+                let (path, status) = walk_error(&e);
+                let path = path.unwrap_or(canonical_root_path.clone());
+                skip_or_fail(&path, MyError::WalkError{path: path.display().to_string(), error: status}, options, skipped)?;
+                continue
+            },
+        };
+        let path = entry.path();
+        if !path.is_file() {
+            continue
+        }
+        let file_token = match file_len(path).and_then(|size| if size > options.max_size { Ok(None) } else { FileToken::new(path, size).map(Some) }) {
+            Ok(Some(t)) => t,
+            Ok(None) => continue,
+            Err(e) => { // 无法读取的文件
+                skip_or_fail(path, e, options, skipped)?;
+                continue
+            },
+        };
+        if let Some(text) = file_token.with_encoding(options.charset).with_binary(options.binary).into_text() {
+            files.push((path.strip_prefix(parent_prefix).unwrap_or(path).to_path_buf(), text));
+        }
+    }
+    Ok(files)
+}
+
+/// 遍历错误对应的路径和错误状态，没有路径的错误（例如.gitignore格式错误）路径为None
+fn walk_error(error: &ignore::Error) -> (Option<PathBuf>, String) {
+    match error {
+        ignore::Error::WithPath{path, err} => (Some(path.clone()), walk_error(err).1),
+        ignore::Error::WithDepth{err, ..} | ignore::Error::WithLineNumber{err, ..} => walk_error(err),
+        ignore::Error::Io(e) => (None, io_status(e)),
+        e => (None, e.to_string()),
+    }
+}
+
+/// 创建遍历指定路径的WalkBuilder，遵循.gitignore，并根据-i、-x过滤文件
+/// -x匹配的文件夹不会再往下遍历，-i只作用于文件
+pub fn walk_builder(root_path: &Path, options: &Options) -> Result<WalkBuilder, MyError> {
+    let include = glob_set(&options.include)?;
+    let exclude = glob_set(&options.exclude)?;
+    let root = root_path.to_path_buf();
+    let mut builder = WalkBuilder::new(root_path);
+    builder.git_ignore(true);
+    if !(options.include.is_empty() && options.exclude.is_empty()) {
+        builder.filter_entry(move |entry| {
+            let path = entry.path();
+            let rltv_path = path.strip_prefix(&root).unwrap_or(path);
+            if rltv_path.as_os_str().is_empty() { // 指定的路径本身
+                return true
+            }
+            let name = entry.file_name();
+            if exclude.is_match(rltv_path) || exclude.is_match(name) {
+                return false
+            }
+            include.is_empty() || entry.file_type().is_some_and(|t| t.is_dir()) || include.is_match(rltv_path) || include.is_match(name)
+        });
+    }
+    Ok(builder)
+}
+
+/// 将多个glob字符串编译为GlobSet
+pub fn glob_set(globs: &[String]) -> Result<GlobSet, MyError> {
+    let mut builder = GlobSetBuilder::new();
+    for g in globs {
+        builder.add(Glob::new(g).map_err(|e| MyError::GlobError{glob: g.to_string(), error: e})?);
+    }
+    builder.build().map_err(|e| MyError::GlobError{glob: globs.join(","), error: e})
+}
+
+/// 遍历已访问的每个路径，如果该路径是当前文件的父级路径，则该路径总token数和总大小要加上当前文件的token数和大小
+/// `rltv_path`是当前文件路径去除前缀后的路径
+fn add_to_parents(dir_tokens: &mut HashMap<usize, (PathBuf, usize, u64)>, idx: usize, rltv_path: &Path, tokens: usize, file_size: u64) {
+    for i in 0..=idx {
+        let dir = dir_tokens.get_mut(&i).unwrap();
+        if rltv_path.starts_with(&dir.0) {
+            dir.1 += tokens;
+            dir.2 += file_size;
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    #[test]
+    fn symlink_loops_and_broken_symlinks() {
+        let dir = std::env::temp_dir().join(format!("tokenum-links-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        write(dir.join("a.txt"), "hello world\n").unwrap();
+        symlink("loop2", dir.join("loop1")).unwrap();
+        symlink("loop1", dir.join("loop2")).unwrap();
+        symlink("nowhere", dir.join("dead")).unwrap();
+        let out = walk_directory(&dir, test_tokenizer(), &Options::default()).unwrap();
+        let mut errors: Vec<(String, String)> = out.errors.iter().map(|(p, s)| (p.file_name().unwrap().to_string_lossy().to_string(), s.clone())).collect();
+        errors.sort();
+        assert_eq!(errors, vec![
+            ("dead".to_string(), "broken symlink".to_string()),
+            ("loop1".to_string(), "symlink loop".to_string()),
+            ("loop2".to_string(), "symlink loop".to_string()),
+        ]);
+        assert!(out.tree.contains("loop1 (symlink loop)") && out.tree.contains("warnings: 3 paths could not be examined"));
+        assert_eq!(out.tokens, 3);
+        remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,143 @@
+use std::fs::write;
+use std::io::{read_to_string, stdin};
+
+use crate::{
+    error::MyError,
+    parse_paras::{ParsedParas, ParsedTruncate},
+    token::{
+        SpecialTokens,
+        count,
+        encode,
+        get_tokenizer,
+        read_text,
+        token_offsets,
+    },
+    tokenizer::Tokenizer,
+};
+
+/// 截断时保留哪部分
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Head,   // 保留开头
+    Tail,   // 保留结尾
+    Middle, // 保留开头和结尾，删除中间
+}
+
+/// 执行truncate子命令，将-f、-s或标准输入的文本截断至-n个token以内，写入-o指定的文件或输出到终端
+pub fn truncate_input(paras: &ParsedParas, truncate: &ParsedTruncate) -> Result<(), MyError> {
+    let bpe = get_tokenizer(&paras.encoding)?;
+    let text = match (&paras.files, &paras.string) {
+        (Some(files), None) if files.len() == 1 => read_text(&files[0], &paras.options)?, // 只有一个输入，无法读取或不是有效文本时返回错误
+        (None, Some(s)) => s.clone(),
+        (None, None) => read_to_string(stdin())?,
+        _ => return Err(MyError::ParaError{para: "truncate only support one input: a single -f file, -s string or stdin".to_string()}),
+    };
+    let (out, tokens, total) = truncate_text(&bpe, &text, truncate.max_tokens, truncate.strategy, &truncate.marker, paras.options.special)?;
+    match &truncate.outfile {
+        Some(o) => write(o, &out).map_err(|e| MyError::WriteFileError{file: o.display().to_string(), error: e})?,
+        None => print!("{}", out),
+    }
+    eprintln!("kept {} of {} tokens", tokens, total);
+    Ok(())
+}
+
+/// 将字符串截断至max_tokens个token以内（包含marker），切分位置都在token边界上，且不会拆开多字节字符
+/// marker中的`{n}`会替换为删除的token数，head会在结尾加上marker，tail会在开头加上marker，middle会在中间加上marker
+/// 返回(截断后的字符串, 截断后的token数, 原始token数)
+pub fn truncate_text(bpe: &dyn Tokenizer, text: &str, max_tokens: usize, strategy: Strategy, marker: &str, special: SpecialTokens) -> Result<(String, usize, usize), MyError> {
+    let offsets = token_offsets(bpe, text, special);
+    let n = offsets.len() - 1; // 原始token数
+    if n <= max_tokens {
+        return Ok((text.to_string(), n, n))
+    }
+    let marker_tokens = encode(bpe, &marker.replace("{n}", &(n - max_tokens).to_string()), special).len(); // 先按删除n-max_tokens个token估算marker的token数
+    if marker_tokens > max_tokens {
+        return Err(MyError::ParaError{para: format!("truncate marker has {} tokens, more than -n {}", marker_tokens, max_tokens)})
+    }
+    let budget = max_tokens - marker_tokens; // 去除marker后可保留的token数
+    // 开头和结尾分别保留的token数
+    let (mut head, mut tail) = match strategy {
+        Strategy::Head => (budget, 0),
+        Strategy::Tail => (0, budget),
+        Strategy::Middle => (budget - budget / 2, budget / 2),
+    };
+    loop {
+        // 开头往前、结尾往后对齐到字符边界
+        let head_end = (0..=head).rev().map(|i| offsets[i]).find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+        let tail_start = (n - tail..=n).map(|i| offsets[i]).find(|&i| text.is_char_boundary(i)).unwrap_or(text.len());
+        let marker = marker.replace("{n}", &(n - head - tail).to_string());
+        let out = match strategy {
+            Strategy::Head => format!("{}{}", &text[..head_end], marker),
+            Strategy::Tail => format!("{}{}", marker, &text[tail_start..]),
+            Strategy::Middle => format!("{}{}{}", &text[..head_end], marker, &text[tail_start..]),
+        };
+        // 拼接后重新计算token数，超过上限则继续减少保留的token
+        let tokens = count(bpe, &out, special);
+        if tokens <= max_tokens || head + tail == 0 {
+            return Ok((out, tokens, n))
+        }
+        if head >= tail {
+            head -= 1;
+        } else {
+            tail -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_tokenizer;
+
+    fn words(n: usize) -> String {
+        (0..n).map(|i| format!("w{}", i)).collect::<Vec<String>>().join(" ")
+    }
+
+    #[test]
+    fn within_budget_unchanged() {
+        let text = words(5);
+        let (out, tokens, total) = truncate_text(test_tokenizer(), &text, 100, Strategy::Head, "[{n} truncated]", SpecialTokens::Allow).unwrap();
+        assert_eq!(out, text);
+        assert_eq!(tokens, total);
+    }
+
+    #[test]
+    fn head_tail_middle() {
+        let text = words(100);
+        for strategy in [Strategy::Head, Strategy::Tail, Strategy::Middle] {
+            let (out, tokens, total) = truncate_text(test_tokenizer(), &text, 20, strategy, " … ", SpecialTokens::Allow).unwrap();
+            assert!(tokens <= 20 && total > 20);
+            assert_eq!(tokens, count(test_tokenizer(), &out, SpecialTokens::Allow));
+            assert!(out.contains(" … "));
+            match strategy {
+                Strategy::Head => assert!(out.starts_with("w0 w1") && out.ends_with(" … ")),
+                Strategy::Tail => assert!(out.starts_with(" … ") && out.ends_with("w99")),
+                Strategy::Middle => assert!(out.starts_with("w0 w1") && out.ends_with("w99")),
+            }
+        }
+    }
+
+    #[test]
+    fn marker_count() {
+        let (out, _, total) = truncate_text(test_tokenizer(), &words(100), 20, Strategy::Head, "[{n}]", SpecialTokens::Allow).unwrap();
+        let kept = count(test_tokenizer(), &out[..out.rfind('[').unwrap()], SpecialTokens::Allow);
+        assert!(out.ends_with(&format!("[{}]", total - kept)));
+    }
+
+    #[test]
+    fn marker_over_budget() {
+        assert!(truncate_text(test_tokenizer(), &words(100), 2, Strategy::Head, "[{n} tokens truncated]", SpecialTokens::Allow).is_err());
+    }
+
+    #[test]
+    fn multi_byte_chars_never_split() {
+        let text = "𝔘𝔙𝔚".repeat(20);
+        for n in 1..10 {
+            for strategy in [Strategy::Head, Strategy::Tail, Strategy::Middle] {
+                let (out, tokens, _) = truncate_text(test_tokenizer(), &text, n, strategy, "", SpecialTokens::Allow).unwrap();
+                assert!(tokens <= n);
+                assert!(out.chars().all(|c| "𝔘𝔙𝔚".contains(c)));
+            }
+        }
+    }
+}
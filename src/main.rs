@@ -1,12 +1,28 @@
+use std::panic::{set_hook, take_hook};
+
 use tokenum::{
-    parse_paras::parse_para,
+    parse_paras::{parse_para, Command},
     error::MyError,
     token::calculate_token,
+    chunk::chunk_files,
+    truncate::truncate_input,
+    pack::pack_files,
+    inspect::inspect_input,
+    decode::decode_input,
+    lines::lines_files,
+    markdown::markdown_files,
+    structure::structure_files,
+    extract::is_extracting,
 };
 
 fn main() {
+    // pdf-extract等提取器的panic会被捕获并显示在tree中，不再打印panic信息，其他panic照常打印
+    let hook = take_hook();
+    set_hook(Box::new(move |info| if !is_extracting() { hook(info) }));
+
     if let Err(e) = run() {
         println!("{}", e); // 这里不要用`{:?}`，会打印结构体而不是打印指定的错误信息
+        std::process::exit(1); // 出错时以非0状态退出，例如--fail-fast遇到无法读取的文件
     }
 }
 
@@ -14,16 +30,32 @@ fn run() -> Result<(), MyError> {
     // 解析参数
     let paras = parse_para()?;
 
-    // 计算token
-    calculate_token(
-        paras.files,
-        paras.string,
-        paras.path,
-        &paras.encoding,
-        paras.max_size,
-        &paras.max_size_str,
-        paras.min_token,
-        paras.max_token,
-        paras.only_valid,
-    )
+    match &paras.command {
+        // 切分chunk
+        Some(Command::Chunk(c)) => chunk_files(&paras, c),
+        // 截断文本
+        Some(Command::Truncate(t)) => truncate_input(&paras, t),
+        // 合并文件
+        Some(Command::Pack(p)) => pack_files(&paras, p),
+        // 显示每个token
+        Some(Command::Inspect(i)) => inspect_input(&paras, i),
+        // 解码token ID
+        Some(Command::Decode(d)) => decode_input(&paras, d),
+        // 每行的token数
+        Some(Command::Lines(l)) => lines_files(&paras, l),
+        // Markdown每个章节的token数
+        Some(Command::Markdown(m)) => markdown_files(&paras, m),
+        // JSON、YAML每个key路径的token数
+        Some(Command::Structure(s)) => structure_files(&paras, s),
+        // 计算token
+        None => calculate_token(
+            paras.files,
+            paras.string,
+            paras.path,
+            &paras.encoding,
+            &paras.options,
+            paras.strict,
+            paras.tools,
+        ),
+    }
 }